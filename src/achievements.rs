@@ -0,0 +1,171 @@
+extern crate toml;
+
+use std::collections::HashSet;
+use OkOrExit;
+
+const ACHIEVEMENTS_FILE: &'static str = "achievements.toml";
+
+/// What happened during the game, achievements are evaluated on them
+pub enum Event {
+    Jump,
+    /// speed of the ball into the wall it bounced on
+    WallHit { intensity: f64, wall: usize },
+    /// speed of the ball after each update
+    Speed { speed: f64 },
+    Death,
+    Goal,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Jump,
+    WallHit,
+    Speed,
+    Death,
+    Goal,
+}
+
+impl Event {
+    fn kind(&self) -> EventKind {
+        match *self {
+            Event::Jump => EventKind::Jump,
+            Event::WallHit { .. } => EventKind::WallHit,
+            Event::Speed { .. } => EventKind::Speed,
+            Event::Death => EventKind::Death,
+            Event::Goal => EventKind::Goal,
+        }
+    }
+}
+
+/// Unlocked when an event of the kind satisfies all the given conditions
+#[derive(Deserialize)]
+pub struct Achievement {
+    /// key in the saved progress
+    pub id: String,
+    /// shown in the toast
+    pub title: String,
+    pub event: EventKind,
+    /// minimal intensity of a wall hit
+    pub min_intensity: Option<f64>,
+    /// minimal speed of the ball
+    pub min_speed: Option<f64>,
+    /// maximal number of jumps since restart
+    pub max_jumps: Option<u32>,
+    /// maximal time since restart
+    pub max_time: Option<f64>,
+    /// number of events of the kind since the last jump, each wall counts once
+    pub count: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct Achievements {
+    #[serde(default)]
+    achievement: Vec<Achievement>,
+}
+
+/// State of the run when an event happens
+pub struct Run {
+    pub jumps: u32,
+    pub time: f64,
+}
+
+/// Count events since the last jump and find the achievements they unlock
+pub struct Tracker {
+    /// counts[i] is the number of events matching ACHIEVEMENTS[i] since the last jump
+    counts: Vec<u32>,
+    /// walls[i] are the walls already counted for ACHIEVEMENTS[i] since the last jump
+    walls: Vec<HashSet<usize>>,
+}
+
+impl Tracker {
+    pub fn new() -> Tracker {
+        Tracker {
+            counts: vec!(0; ACHIEVEMENTS.len()),
+            walls: vec!(HashSet::new(); ACHIEVEMENTS.len()),
+        }
+    }
+    /// Achievements satisfied by the event
+    pub fn handle(&mut self, event: &Event, run: &Run) -> Vec<&'static Achievement> {
+        if let Event::Jump = *event {
+            for count in &mut self.counts {
+                *count = 0;
+            }
+            for walls in &mut self.walls {
+                walls.clear();
+            }
+        }
+        let mut unlocked = vec!();
+        for ((achievement, count), walls) in ACHIEVEMENTS.iter().zip(&mut self.counts).zip(&mut self.walls) {
+            if achievement.event != event.kind() {
+                continue
+            }
+            match *event {
+                Event::WallHit { intensity, .. } if achievement.min_intensity.map_or(false, |min| intensity < min) => continue,
+                Event::Speed { speed } if achievement.min_speed.map_or(false, |min| speed < min) => continue,
+                _ => (),
+            }
+            if achievement.max_jumps.map_or(false, |max| run.jumps > max)
+                || achievement.max_time.map_or(false, |max| run.time > max) {
+                continue
+            }
+            if let Event::WallHit { wall, .. } = *event {
+                if !walls.insert(wall) {
+                    continue
+                }
+            }
+            *count += 1;
+            if achievement.count.map_or(true, |min| *count >= min) {
+                unlocked.push(achievement);
+            }
+        }
+        unlocked
+    }
+}
+
+enum Error {
+    Io(::std::io::Error),
+    Toml(toml::de::Error),
+}
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Error {
+        Error::Toml(err)
+    }
+}
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        use self::Error::*;
+        match *self {
+            Io(ref e) => write!(fmt, "file `{}`: io error: {}", ACHIEVEMENTS_FILE, e),
+            Toml(ref e) => write!(fmt, "file `{}`: toml decode error: {}", ACHIEVEMENTS_FILE, e),
+        }
+    }
+}
+
+#[cfg(feature = "exclude_all")]
+fn read_achievements_file() -> Result<String, Error> {
+    use std::fs::File;
+    use std::io::Read;
+    let mut achievements = String::new();
+    File::open(ACHIEVEMENTS_FILE)?.read_to_string(&mut achievements)?;
+    Ok(achievements)
+}
+
+#[cfg(not(feature = "exclude_all"))]
+fn read_achievements_file() -> Result<&'static str, Error> {
+    Ok(include_str!("../achievements.toml"))
+}
+
+fn load_achievements() -> Result<Vec<Achievement>, Error> {
+    let achievements = read_achievements_file()?;
+    Ok(toml::from_str::<Achievements>(&achievements)?.achievement)
+}
+
+lazy_static! {
+    pub static ref ACHIEVEMENTS: Vec<Achievement> = load_achievements().ok_or_exit();
+}
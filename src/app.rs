@@ -1,120 +1,1000 @@
-use configuration::CFG;
-use map::MAP;
+use configuration::{CFG, Mode, Tether};
+use map::{MAP, Kind, Pickup, Gravity};
 use math::*;
 use physics::{Body, Shape, Collision};
 use spatial_hashing::SpatialHashing;
 use graphics::{self, Layer, Transformed};
 use audio::Audio;
+use hazard::{Hazard, Behavior};
+use progress::{Progress, Medal, ReplayJump};
+use save_state::{self, SaveState, SaveStates, HazardState, HookState};
+use endless::{self, Climb};
+use achievements::{Event, Run, Tracker};
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::PI;
 
 #[derive(Debug, Clone)]
 struct Effect {
     pos: [f64;2],
     angle: f64,
     timer: f64,
+    /// radius of the ball that jumped
+    radius: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    pos: [f64; 2],
+    vel: [f64; 2],
+    radius: f64,
+    timer: f64,
+    duration: f64,
+    color: [f32; 4],
+}
+
+#[derive(Clone)]
+struct Ball {
+    body: Body,
+    vel: [f64; 2],
+    acc: [f64; 2],
+    /// rotation of the ball, only used to draw the marker
+    angle: f64,
+    /// angular velocity, counterclockwise in radians per second
+    spin: f64,
+    air_jump: bool,
+    /// remaining time before the ball gets back its default radius
+    size_timer: Option<f64>,
+    /// the center of the ball is in a water volume
+    in_water: bool,
+    /// gravity triggers the ball was in on last update
+    gravity_triggers: Vec<usize>,
+    hook: Option<Hook>,
+}
+
+/// Grappling hook attached to a wall, the ball swings at the end of the rope
+#[derive(Clone)]
+struct Hook {
+    wall: usize,
+    anchor: [f64; 2],
+    length: f64,
+}
+
+impl Ball {
+    fn new(pos: [f64; 2]) -> Ball {
+        Ball {
+            body: Body {
+                pos: pos,
+                shape: Shape::Circle(CFG.gameplay.ball_radius),
+            },
+            vel: [0., 0.],
+            acc: [0., 0.],
+            angle: 0.,
+            spin: 0.,
+            air_jump: true,
+            size_timer: None,
+            in_water: false,
+            gravity_triggers: vec!(),
+            hook: None,
+        }
+    }
+    fn radius(&self) -> f64 {
+        match self.body.shape {
+            Shape::Circle(radius) => radius,
+            _ => unreachable!(),
+        }
+    }
+    fn set_radius(&mut self, radius: f64) {
+        self.body.shape = Shape::Circle(radius);
+    }
+}
+
+/// A ball and its input
+struct Player {
+    ball: Ball,
+    jump_angle: f64,
+    /// 1 to reel the hook in, -1 to reel it out
+    reel: f64,
+    color: [f32; 4],
+    /// position and angle of the ball before the last update, drawing interpolates from them
+    previous_pos: [f64; 2],
+    previous_angle: f64,
+}
+
+impl Player {
+    fn new(index: usize) -> Player {
+        let colors = &CFG.graphics.player_colors;
+        Player {
+            ball: Ball::new(start()),
+            jump_angle: 0.,
+            reel: 0.,
+            color: if index == 0 || colors.is_empty() { CFG.graphics.ball_color } else { colors[(index - 1) % colors.len()] },
+            previous_pos: start(),
+            previous_angle: 0.,
+        }
+    }
+    /// Forget the previous state so the ball isn't drawn sliding to where it was moved
+    fn snap(&mut self) {
+        self.previous_pos = self.ball.body.pos;
+        self.previous_angle = self.ball.angle;
+    }
+    /// Position and angle of the ball drawn between the previous and the current update
+    fn drawn(&self, interpolation: f64) -> ([f64; 2], f64) {
+        let pos = add(mul(1. - interpolation, self.previous_pos), mul(interpolation, self.ball.body.pos));
+        let angle = (1. - interpolation)*self.previous_angle + interpolation*self.ball.angle;
+        (pos, angle)
+    }
+}
+
+/// State restored when rewinding
+#[derive(Clone)]
+struct Snapshot {
+    balls: Vec<Ball>,
+    effects: Vec<Effect>,
+    gravity_angle: f64,
+    camera_angle: f64,
+    /// consumed pickups, shattered walls and open doors
+    disabled: Vec<usize>,
+    switches: HashMap<usize, SwitchState>,
+}
+
+/// Springs and boost pads are squashed for a moment when hit
+#[derive(Debug, Clone)]
+struct Squash {
+    timer: f64,
+    /// normal of the contact, pointing toward the ball
+    normal: [f64; 2],
+}
+
+impl Squash {
+    fn new(normal: [f64; 2]) -> Squash {
+        Squash {
+            timer: CFG.graphics.squash_timer,
+            normal: normal,
+        }
+    }
+    /// Squash the body along the normal, the side opposite to the contact doesn't move
+    fn apply(&self, body: &mut Body) {
+        let k = 1. - CFG.graphics.squash_amount*self.timer/CFG.graphics.squash_timer;
+        match body.shape {
+            Shape::Circle(ref mut radius) => *radius *= k,
+            Shape::Rectangle(ref mut width, ref mut height) => if self.normal[0].abs() > self.normal[1].abs() {
+                body.pos[0] -= self.normal[0].signum()*(1. - k)*(*width)/2.;
+                *width *= k;
+            } else {
+                body.pos[1] -= self.normal[1].signum()*(1. - k)*(*height)/2.;
+                *height *= k;
+            },
+            Shape::OrientedRectangle(ref mut width, ref mut height, angle) => {
+                let normal = rotate(self.normal, -angle);
+                let shift = if normal[0].abs() > normal[1].abs() {
+                    let shift = [-normal[0].signum()*(1. - k)*(*width)/2., 0.];
+                    *width *= k;
+                    shift
+                } else {
+                    let shift = [0., -normal[1].signum()*(1. - k)*(*height)/2.];
+                    *height *= k;
+                    shift
+                };
+                body.pos = add(body.pos, rotate(shift, angle));
+            },
+            Shape::Polygon(ref mut vertices) => {
+                // the vertices farthest from the contact don't move
+                let back = vertices.iter().map(|&v| dot(v, self.normal)).fold(::std::f64::INFINITY, f64::min);
+                for v in vertices.iter_mut() {
+                    let depth = dot(*v, self.normal) - back;
+                    *v = sub(*v, mul((1. - k)*depth, self.normal));
+                }
+            },
+            // the parts are squashed together like a single polygon
+            Shape::Compound(ref mut parts) => {
+                let back = parts.iter()
+                    .flat_map(|part| match part.shape {
+                        Shape::Polygon(ref vertices) => vertices.iter().map(|&v| dot(add(part.pos, v), self.normal)).collect(),
+                        _ => vec!(),
+                    })
+                    .fold(::std::f64::INFINITY, f64::min);
+                for part in parts.iter_mut() {
+                    let pos = part.pos;
+                    if let Shape::Polygon(ref mut vertices) = part.shape {
+                        for v in vertices.iter_mut() {
+                            let depth = dot(add(pos, *v), self.normal) - back;
+                            *v = sub(*v, mul((1. - k)*depth, self.normal));
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Shown for a moment when an achievement is unlocked
+struct Toast {
+    title: &'static str,
+    timer: f64,
+}
+
+/// Shown when the goal is reached
+#[derive(Debug, Clone)]
+struct Results {
+    time: f64,
+    jumps: u32,
+    time_medal: Option<Medal>,
+    jumps_medal: Option<Medal>,
+    /// ran out of jumps in fewest jumps mode
+    failed: bool,
+    /// ran out of lives
+    game_over: bool,
+    new_best: bool,
+    /// a save state was loaded during the run
+    practice: bool,
+    /// height reached in endless mode
+    height: Option<f64>,
+    /// first player to reach the goal in a race
+    winner: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SwitchState {
+    /// the ball was touching the switch on last update
+    pressed: bool,
+    /// the switch has been activated at least once
+    used: bool,
+    /// linked doors are opened
+    open: bool,
+    /// remaining time before doors close
+    timer: Option<f64>,
 }
 
 pub struct App {
     walls: SpatialHashing,
-    ball: Body,
-    ball_vel: [f64; 2],
-    ball_acc: [f64; 2],
-    air_jump: bool,
+    /// kinds[id] is the kind of the wall id
+    kinds: Vec<Kind>,
+    /// the hook can attach to the wall id
+    hookable: Vec<bool>,
+    switches: HashMap<usize, SwitchState>,
+    squashes: HashMap<usize, Squash>,
+    attractors: Vec<usize>,
+    hazards: Vec<Hazard>,
+    /// the ball dies outside, none in endless mode
+    bounds: Option<Body>,
+    players: Vec<Player>,
+    /// shared by all players, unused when lives are disabled
+    lives: u32,
     effects: Vec<Effect>,
-    jump_angle: f64,
+    particles: Vec<Particle>,
+    /// last snapshots, the most recent at the back
+    history: VecDeque<Snapshot>,
+    pub rewinding: bool,
+    /// fraction of the update step elapsed since the last update, in [0, 1]
+    pub interpolation: f64,
+    /// direction of gravity
+    gravity_angle: f64,
+    camera_angle: f64,
+    /// time since start, used for animations
+    time: f64,
+    /// time since restart
+    run_time: f64,
+    /// jumps since restart, in fewest jumps mode every attempt counts
+    jumps: u32,
+    /// updates since restart
+    ticks: u64,
+    /// jump attempts since restart
+    replay: Vec<ReplayJump>,
+    results: Option<Results>,
+    progress: Progress,
+    tracker: Tracker,
+    /// unlocked achievements, the one shown at the front
+    toasts: VecDeque<Toast>,
+    save_states: SaveStates,
+    /// slot used to save and load states
+    slot: usize,
+    /// a save state was loaded since restart
+    practice: bool,
+    /// generated chunks in endless mode
+    climb: Option<Climb>,
     pub must_quit: bool,
     audio: Audio,
 }
 
 impl App {
     pub fn new(audio: Audio) -> App {
+        // in endless mode walls are added as the ball climbs
+        let (bodies, kinds, hookable, hazards) = if CFG.gameplay.mode == Mode::Endless {
+            (vec!(), vec!(), vec!(), vec!())
+        } else {
+            (MAP.bodies.clone(), MAP.kinds.clone(), MAP.hookable.clone(), MAP.hazards.clone())
+        };
         App {
-            ball: Body {
-                pos: MAP.start,
-                shape: Shape::Circle(CFG.gameplay.ball_radius),
+            bounds: if CFG.gameplay.mode == Mode::Endless { None } else { bounds(&bodies) },
+            lives: CFG.gameplay.lives,
+            players: (0..CFG.multiplayer.players.max(if tethered() { 2 } else { 1 })).map(Player::new).collect(),
+            walls: SpatialHashing::new(CFG.physics.unit, &bodies),
+            switches: kinds.iter()
+                .enumerate()
+                .filter_map(|(id, kind)| match *kind {
+                    Kind::Switch(_) => Some((id, SwitchState::default())),
+                    _ => None,
+                })
+                .collect(),
+            squashes: HashMap::new(),
+            attractors: kinds.iter()
+                .enumerate()
+                .filter_map(|(id, kind)| match *kind {
+                    Kind::Attractor(..) => Some(id),
+                    _ => None,
+                })
+                .collect(),
+            kinds: kinds,
+            hookable: hookable,
+            hazards: hazards,
+            gravity_angle: -PI/2.,
+            camera_angle: 0.,
+            time: 0.,
+            run_time: 0.,
+            jumps: 0,
+            ticks: 0,
+            replay: vec!(),
+            results: None,
+            progress: Progress::load().unwrap_or_else(|e| {
+                println!("WARNING: {}", e);
+                Progress::default()
+            }),
+            tracker: Tracker::new(),
+            toasts: VecDeque::new(),
+            save_states: if CFG.practice.save_to_disk {
+                SaveStates::load().unwrap_or_else(|e| {
+                    println!("WARNING: {}", e);
+                    SaveStates::default()
+                })
+            } else {
+                SaveStates::default()
             },
-            ball_vel: [0., 0.],
-            ball_acc: [0., 0.],
-            walls: SpatialHashing::new(CFG.physics.unit, &MAP.bodies),
-            jump_angle: 0.,
+            slot: 0,
+            practice: false,
+            climb: if CFG.gameplay.mode == Mode::Endless { Some(Climb::new()) } else { None },
             effects: vec!(),
-            air_jump: true,
+            particles: vec!(),
+            history: VecDeque::new(),
+            rewinding: false,
+            interpolation: 1.,
             must_quit: false,
             audio: audio,
         }
     }
+    /// Frame all the players, zoom out as they spread apart
     pub fn camera(&self) -> graphics::Camera {
+        let first = self.players[0].drawn(self.interpolation).0;
+        let (min, max) = self.players.iter().fold((first, first), |(min, max), player| {
+            let pos = player.drawn(self.interpolation).0;
+            ([min[0].min(pos[0]), min[1].min(pos[1])], [max[0].max(pos[0]), max[1].max(pos[1])])
+        });
+        let center = mul(0.5, add(min, max));
+        let spread = norm(sub(max, min))/2. + CFG.camera.spread_margin;
         graphics::Camera {
-            x: self.ball.pos[0] as f32,
-            y: self.ball.pos[1] as f32,
-            zoom: CFG.camera.zoom as f32,
+            x: center[0] as f32,
+            y: center[1] as f32,
+            zoom: CFG.camera.zoom.min(1./spread) as f32,
+            rotation: self.camera_angle as f32,
         }
     }
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
     pub fn draw(&mut self, frame: &mut graphics::Frame) {
         let (w, h) = {
             let (w, h) = frame.size();
             (w as f64, h as f64)
         };
 
-        let field_of_view = Body {
-            pos: self.ball.pos,
-            shape: Shape::Rectangle(2./(h/w).min(1.)/CFG.camera.zoom, 2./(w/h).min(1.)/CFG.camera.zoom),
+        let field_of_view = {
+            let camera = self.camera();
+            let zoom = camera.zoom as f64;
+            let width = 2./(h/w).min(1.)/zoom;
+            let height = 2./(w/h).min(1.)/zoom;
+            let (c, s) = (self.camera_angle.cos().abs(), self.camera_angle.sin().abs());
+            Body {
+                pos: [camera.x as f64, camera.y as f64],
+                shape: Shape::Rectangle(width*c + height*s, width*s + height*c),
+            }
         };
 
-        for b in &self.walls.get_on_body(&field_of_view) {
-            match b.shape {
-                Shape::Circle(radius) => frame.draw_circle(b.pos[0] as f32, b.pos[1] as f32, radius as f32, Layer::World, CFG.graphics.wall_color),
-                Shape::Rectangle(width, height) => frame.draw_rectangle(b.pos[0] as f32, b.pos[1] as f32, width as f32, height as f32, Layer::World, CFG.graphics.wall_color),
+        let mut waters = vec!();
+        for id in self.walls.get_all_on_body(&field_of_view) {
+            // only open doors are drawn while disabled
+            if !self.walls.is_enabled(id) {
+                match self.kinds[id] {
+                    Kind::Door => (),
+                    _ => continue,
+                }
+            }
+            let color = match self.kinds[id] {
+                // drawn over the ball
+                Kind::Water => {
+                    waters.push(id);
+                    continue
+                },
+                Kind::Wall => CFG.graphics.wall_color,
+                Kind::Door if self.walls.is_enabled(id) => CFG.graphics.door_color,
+                Kind::Door => CFG.graphics.open_door_color,
+                Kind::Switch(_) => CFG.graphics.switch_color,
+                Kind::OneWay(_) => CFG.graphics.one_way_color,
+                Kind::Spring(..) => CFG.graphics.spring_color,
+                Kind::Boost(_) => CFG.graphics.boost_color,
+                Kind::Breakable(_) => CFG.graphics.breakable_color,
+                Kind::Conveyor(_) => CFG.graphics.conveyor_color,
+                Kind::Attractor(strength, _) if strength < 0. => CFG.graphics.repulsor_color,
+                Kind::Attractor(..) => CFG.graphics.attractor_color,
+                Kind::Pickup(Pickup::Size(scale, _)) if scale < 1. => CFG.graphics.shrink_color,
+                Kind::Pickup(Pickup::Size(..)) => CFG.graphics.grow_color,
+                Kind::Pickup(Pickup::Life) => CFG.graphics.life_color,
+                Kind::Gravity(_) => CFG.graphics.gravity_color,
+                Kind::Goal => CFG.graphics.goal_color,
+            };
+            let mut b = self.walls.get(id).clone();
+            if let Kind::Attractor(_, range) = self.kinds[id] {
+                frame.draw_circle(b.pos[0] as f32, b.pos[1] as f32, range as f32, Layer::World, CFG.graphics.attractor_range_color);
+            }
+            if let Some(squash) = self.squashes.get(&id) {
+                squash.apply(&mut b);
+            }
+            draw_body(frame, &b, color);
+            if let Kind::Conveyor(speed) = self.kinds[id] {
+                self.draw_conveyor(frame, &b, speed);
+            }
+            if let Kind::Gravity(Gravity::To(angle)) = self.kinds[id] {
+                let (width, height) = match b.shape {
+                    Shape::Rectangle(width, height) | Shape::OrientedRectangle(width, height, _) => (width, height),
+                    _ => b.shape.size(),
+                };
+                let half_length = (width.min(height)*0.4) as f32;
+                let transform = graphics::Transformation::identity()
+                    .translate(b.pos[0] as f32, b.pos[1] as f32)
+                    .rotate(angle as f32)
+                    .translate(half_length, 0.)
+                    .scale(half_length, half_length/8.);
+                frame.draw_quad(transform, Layer::World, CFG.graphics.gravity_arrow_color);
             }
         }
 
-        frame.draw_circle(self.ball.pos[0] as f32, self.ball.pos[1] as f32, CFG.gameplay.ball_radius as f32, Layer::World, CFG.graphics.ball_color);
+        for hazard in &self.hazards {
+            draw_body(frame, &hazard.body, CFG.graphics.hazard_color);
+        }
+
+        if tethered() {
+            let (a, b) = (self.players[0].drawn(self.interpolation).0, self.players[1].drawn(self.interpolation).0);
+            draw_line(frame, a, b, CFG.graphics.tether_thickness, CFG.graphics.tether_color);
+        }
+        for player in &self.players {
+            if let Some(ref hook) = player.ball.hook {
+                draw_line(frame, player.drawn(self.interpolation).0, hook.anchor, CFG.graphics.hook_thickness, CFG.graphics.hook_color);
+            }
+        }
+
+        for player in &self.players {
+            let (pos, angle) = player.drawn(self.interpolation);
+            let radius = player.ball.radius();
+            frame.draw_circle(pos[0] as f32, pos[1] as f32, radius as f32, Layer::World, player.color);
+            let marker = add(pos, from_polar([radius*0.6, angle]));
+            frame.draw_circle(marker[0] as f32, marker[1] as f32, (radius*0.2) as f32, Layer::World, CFG.graphics.spin_marker_color);
+        }
+
+        for particle in &self.particles {
+            let mut color = particle.color;
+            color[3] *= (particle.timer/particle.duration) as f32;
+            frame.draw_square(particle.pos[0] as f32, particle.pos[1] as f32, particle.radius as f32, Layer::World, color);
+        }
+
+        for id in waters {
+            draw_body(frame, self.walls.get(id), CFG.graphics.water_color);
+        }
 
         for effect in &self.effects {
             let mut color = CFG.graphics.effect_color;
             color[3] *= (effect.timer/CFG.graphics.effect_timer) as f32;
-            let half_width = CFG.gameplay.ball_radius  as f32;
+            let half_width = effect.radius as f32;
             let half_height = CFG.graphics.effect_thickness/2.;
 
             let transform = graphics::Transformation::identity()
                 .translate(effect.pos[0] as f32, effect.pos[1] as f32)
                 .rotate(effect.angle as f32)
-                .translate(-(CFG.gameplay.ball_radius as f32 + half_height), 0.)
+                .translate(-(effect.radius as f32 + half_height), 0.)
                 .scale(half_height, half_width);
             frame.draw_quad(transform, Layer::World, color);
         }
+
+        if let Some(ref results) = self.results {
+            self.draw_results(frame, results);
+        } else if CFG.gameplay.mode == Mode::FewestJumps {
+            self.draw_jump_counter(frame);
+        } else if let Some(ref climb) = self.climb {
+            self.draw_height(frame, climb.score());
+        }
+        if self.results.is_none() && CFG.gameplay.lives > 0 {
+            self.draw_lives(frame);
+        }
+        if let Some(toast) = self.toasts.front() {
+            self.draw_toast(frame, toast.title);
+        }
+    }
+    fn draw_results(&self, frame: &mut graphics::Frame, results: &Results) {
+        let (w, h) = frame.size();
+        let ratio = h as f32/w as f32;
+        let color = CFG.graphics.text_color;
+        frame.draw_rectangle(0., 0., 2., 2.*ratio, Layer::Billboard, CFG.graphics.results_background_color);
+        let (title, title_color) = if results.failed {
+            ("OUT OF JUMPS".to_string(), color)
+        } else if results.game_over {
+            ("GAME OVER".to_string(), color)
+        } else if results.height.is_some() {
+            ("CLIMB OVER".to_string(), color)
+        } else if let Some(winner) = results.winner {
+            (format!("PLAYER {} WINS", winner + 1), self.players[winner].color)
+        } else {
+            ("LEVEL COMPLETE".to_string(), color)
+        };
+        frame.draw_text(&title, 0., 0.3, 0.1, Layer::Billboard, title_color);
+
+        let time = (format!("TIME {:.2}", results.time), results.time_medal);
+        let jumps = (format!("JUMPS {}", results.jumps), results.jumps_medal);
+        // the ranking criteria comes first
+        let lines = if let Some(height) = results.height {
+            [(format!("HEIGHT {:.0}", height), None), time]
+        } else if CFG.gameplay.mode == Mode::FewestJumps {
+            [jumps, time]
+        } else {
+            [time, jumps]
+        };
+        for (&(ref text, medal), &y) in lines.iter().zip(&[0.1, -0.05]) {
+            frame.draw_text(text, 0., y, 0.07, Layer::Billboard, color);
+            if let Some(medal) = medal {
+                frame.draw_circle(0.5, y, 0.04, Layer::Billboard, medal_color(medal));
+            }
+        }
+        if results.practice {
+            frame.draw_text("PRACTICE RUN", 0., -0.17, 0.05, Layer::Billboard, color);
+        } else if results.new_best {
+            frame.draw_text("NEW BEST", 0., -0.17, 0.05, Layer::Billboard, CFG.graphics.gold_color);
+        }
+        frame.draw_text("JUMP TO RESTART", 0., -0.3, 0.04, Layer::Billboard, color);
+    }
+    /// Jump count on top of the screen
+    fn draw_jump_counter(&self, frame: &mut graphics::Frame) {
+        let (w, h) = frame.size();
+        let top = h as f32/w as f32;
+        let text = match CFG.gameplay.jump_cap {
+            Some(cap) => format!("{}/{}", self.jumps, cap),
+            None => format!("{}", self.jumps),
+        };
+        frame.draw_text(&text, 0., top - 0.12, 0.12, Layer::Billboard, CFG.graphics.text_color);
+    }
+    /// Unlocked achievement on the bottom of the screen
+    fn draw_toast(&self, frame: &mut graphics::Frame, title: &str) {
+        let (w, h) = frame.size();
+        let bottom = -(h as f32/w as f32);
+        frame.draw_rectangle(0., bottom + 0.1, 1.2, 0.14, Layer::Billboard, CFG.graphics.toast_background_color);
+        frame.draw_text(&format!("ACHIEVEMENT: {}", title), 0., bottom + 0.1, 0.05, Layer::Billboard, CFG.graphics.toast_color);
+    }
+    /// A dot for each remaining life on the top left of the screen
+    fn draw_lives(&self, frame: &mut graphics::Frame) {
+        let (w, h) = frame.size();
+        let top = h as f32/w as f32;
+        for i in 0..self.lives {
+            frame.draw_circle(-0.94 + i as f32*0.07, top - 0.06, 0.025, Layer::Billboard, CFG.graphics.life_color);
+        }
+    }
+    /// Height reached on top of the screen
+    fn draw_height(&self, frame: &mut graphics::Frame, height: f64) {
+        let (w, h) = frame.size();
+        let top = h as f32/w as f32;
+        let text = format!("{:.0}", height);
+        frame.draw_text(&text, 0., top - 0.12, 0.12, Layer::Billboard, CFG.graphics.text_color);
+    }
+    /// Stripes moving along the surface: for a positive speed the surface
+    /// goes clockwise so the top of a horizontal conveyor goes right
+    fn draw_conveyor(&self, frame: &mut graphics::Frame, body: &Body, speed: f64) {
+        let spacing = CFG.graphics.conveyor_stripe_spacing;
+        let offset = ((self.time*speed) % spacing + spacing) % spacing;
+        let color = CFG.graphics.conveyor_stripe_color;
+        let (width, height, angle) = match body.shape {
+            Shape::Circle(radius) => {
+                let n = (2.*PI*radius/spacing).floor().max(1.);
+                for i in 0..n as usize {
+                    let angle = (i as f64 + offset/spacing)*2.*PI/n;
+                    // clockwise
+                    let pos = add(body.pos, from_polar([radius*0.8, -angle]));
+                    frame.draw_circle(pos[0] as f32, pos[1] as f32, (radius*0.1) as f32, Layer::World, color);
+                }
+                return
+            },
+            Shape::Rectangle(width, height) => (width, height, 0.),
+            Shape::OrientedRectangle(width, height, angle) => (width, height, angle),
+            // stripes don't follow the edges of polygons
+            Shape::Polygon(_) | Shape::Compound(_) => return,
+        };
+        // stripes go along the longest side in the frame of the rectangle
+        let horizontal = width >= height;
+        let (length, thickness) = if horizontal { (width, height) } else { (height, width) };
+        let stripe = CFG.graphics.conveyor_stripe_width;
+        let mut x = offset - spacing;
+        while x < length {
+            let start = x.max(0.);
+            let end = (x + stripe).min(length);
+            if end > start {
+                // for a vertical conveyor the left side goes up
+                let along = -length/2. + (start + end)/2.;
+                let (cx, cy, w, h) = if horizontal {
+                    (along, 0., end - start, thickness)
+                } else {
+                    (0., along, thickness, end - start)
+                };
+                let transform = graphics::Transformation::identity()
+                    .translate(body.pos[0] as f32, body.pos[1] as f32)
+                    .rotate(angle as f32)
+                    .translate(cx as f32, cy as f32)
+                    .scale((w/2.) as f32, (h/2.) as f32);
+                frame.draw_quad(transform, Layer::World, color);
+            }
+            x += spacing;
+        }
     }
     pub fn update(&mut self, dt: f64) {
-        use ::std::f64::consts::PI;
+        for player in &mut self.players {
+            player.snap();
+        }
+        self.time += dt;
+        let shown = match self.toasts.front_mut() {
+            Some(toast) => {
+                toast.timer -= dt;
+                toast.timer > 0.
+            },
+            None => true,
+        };
+        if !shown {
+            self.toasts.pop_front();
+        }
+        if self.results.is_some() {
+            return
+        }
+        self.run_time += dt;
+
+        if self.rewinding && rewind_enabled() {
+            if let Some(snapshot) = self.history.pop_back() {
+                for (player, ball) in self.players.iter_mut().zip(snapshot.balls) {
+                    player.ball = ball;
+                }
+                self.effects = snapshot.effects;
+                self.gravity_angle = snapshot.gravity_angle;
+                self.camera_angle = snapshot.camera_angle;
+                self.walls.enable_all();
+                for id in snapshot.disabled {
+                    self.walls.set_enabled(id, false);
+                }
+                self.switches = snapshot.switches;
+            }
+            return
+        }
+        if rewind_enabled() {
+            self.history.push_back(Snapshot {
+                balls: self.players.iter().map(|player| player.ball.clone()).collect(),
+                effects: self.effects.clone(),
+                gravity_angle: self.gravity_angle,
+                camera_angle: self.camera_angle,
+                disabled: self.walls.disabled(),
+                switches: self.switches.clone(),
+            });
+            while self.history.len() as f64*dt > CFG.rewind.duration {
+                self.history.pop_front();
+            }
+        }
+
+        self.ticks += 1;
+
+        if self.climb.is_some() {
+            self.update_climb();
+            if self.results.is_some() {
+                return
+            }
+        }
 
         for effect in &mut self.effects {
             effect.timer -= dt;
         }
         self.effects.retain(|e| e.timer > 0.);
 
-        let mut force = [0., -CFG.gameplay.gravity];
+        for squash in self.squashes.values_mut() {
+            squash.timer -= dt;
+        }
+        self.squashes.retain(|_, s| s.timer > 0.);
+
+        for player in &mut self.players {
+            let ball = &mut player.ball;
+            if let Some(timer) = ball.size_timer.take() {
+                if timer > dt {
+                    ball.size_timer = Some(timer - dt);
+                } else {
+                    ball.set_radius(CFG.gameplay.ball_radius);
+                }
+            }
+        }
 
-        force[0] -= CFG.gameplay.damping*self.ball_vel[0];
-        force[1] -= CFG.gameplay.damping*self.ball_vel[1];
+        for particle in &mut self.particles {
+            particle.timer -= dt;
+            particle.vel = add(particle.vel, from_polar([dt*CFG.gameplay.gravity, self.gravity_angle]));
+            particle.pos[0] += dt*particle.vel[0];
+            particle.pos[1] += dt*particle.vel[1];
+        }
+        self.particles.retain(|p| p.timer > 0.);
 
-        self.ball_acc = force;
+        if CFG.camera.rotate {
+            // "down" on screen follows gravity
+            let target = self.gravity_angle + PI/2.;
+            let mut delta = (target - self.camera_angle) % (2.*PI);
+            if delta > PI {
+                delta -= 2.*PI;
+            } else if delta < -PI {
+                delta += 2.*PI;
+            }
+            let step = CFG.camera.rotation_speed*dt;
+            self.camera_angle += delta.max(-step).min(step);
+        }
 
-        self.ball_vel[0] += dt*self.ball_acc[0];
-        self.ball_vel[1] += dt*self.ball_acc[1];
+        for hazard in &mut self.hazards {
+            // chasers go after the closest ball
+            let target = self.players.iter()
+                .map(|player| player.ball.body.pos)
+                .min_by(|a, b| norm(sub(*a, hazard.body.pos)).partial_cmp(&norm(sub(*b, hazard.body.pos))).unwrap())
+                .unwrap();
+            hazard.update(dt, target);
+        }
 
-        self.ball.pos[0] += dt*self.ball_vel[0];
-        self.ball.pos[1] += dt*self.ball_vel[1];
+        let mut touched_switches = vec!();
+        for i in 0..self.players.len() {
+            if !self.update_player(i, dt, &mut touched_switches) {
+                return
+            }
+        }
+        if tethered() {
+            self.update_tether(dt);
+        }
+        self.update_switches(dt, &touched_switches);
+    }
+    /// Reel the rope and keep the ball at its end, moving away from the anchor is cancelled
+    /// so the ball swings as a pendulum
+    fn update_hook(&mut self, i: usize, dt: f64) {
+        let wall = match self.players[i].ball.hook {
+            Some(ref hook) => hook.wall,
+            None => return,
+        };
+        // the wall was opened or shattered
+        if !self.walls.is_enabled(wall) {
+            self.players[i].ball.hook = None;
+            return
+        }
+        let reel = self.players[i].reel;
+        let ball = &mut self.players[i].ball;
+        if let Some(ref mut hook) = ball.hook {
+            hook.length = (hook.length - reel*CFG.hook.reel_speed*dt).max(CFG.hook.min_length).min(CFG.hook.length);
+            let delta = sub(ball.body.pos, hook.anchor);
+            let distance = norm(delta);
+            if distance > hook.length {
+                let normal = mul(1./distance, delta);
+                ball.body.pos = add(hook.anchor, mul(hook.length, normal));
+                let radial = dot(ball.vel, normal);
+                if radial > 0. {
+                    ball.vel = sub(ball.vel, mul(radial, normal));
+                }
+            }
+        }
+    }
+    /// Constraint between the first two balls, solved after the wall collisions
+    fn update_tether(&mut self, dt: f64) {
+        let (first, others) = self.players.split_at_mut(1);
+        let (a, b) = (&mut first[0].ball, &mut others[0].ball);
+        let delta = sub(b.body.pos, a.body.pos);
+        let distance = norm(delta);
+        if distance == 0. {
+            return
+        }
+        let normal = mul(1./distance, delta);
+        // speed at which the balls move apart
+        let separation = dot(sub(b.vel, a.vel), normal);
+        let stretch = distance - CFG.multiplayer.tether_length;
+
+        let impulse = match CFG.multiplayer.tether {
+            Tether::Rope => {
+                if stretch <= 0. {
+                    return
+                }
+                // balls have the same mass so each one moves half the way
+                a.body.pos = add(a.body.pos, mul(stretch/2., normal));
+                b.body.pos = sub(b.body.pos, mul(stretch/2., normal));
+                separation.max(0.)/2.
+            },
+            Tether::Spring => {
+                let force = CFG.multiplayer.tether_stiffness*stretch + CFG.multiplayer.tether_damping*separation;
+                force*dt
+            },
+            Tether::None => unreachable!(),
+        };
+        a.vel = add(a.vel, mul(impulse, normal));
+        b.vel = sub(b.vel, mul(impulse, normal));
+    }
+    /// Move the ball of the player and resolve its collisions,
+    /// return false if the level was restarted
+    fn update_player(&mut self, i: usize, dt: f64, touched_switches: &mut Vec<usize>) -> bool {
+        let mut force = from_polar([CFG.gameplay.gravity, self.gravity_angle]);
+        {
+            let ball = &self.players[i].ball;
+            let damping = if ball.in_water {
+                force = add(force, from_polar([CFG.gameplay.water_buoyancy*CFG.gameplay.gravity, self.gravity_angle + PI]));
+                CFG.gameplay.water_damping
+            } else {
+                CFG.gameplay.damping
+            };
+            force[0] -= damping*ball.vel[0];
+            force[1] -= damping*ball.vel[1];
+
+            for &id in &self.attractors {
+                if let Kind::Attractor(strength, range) = self.kinds[id] {
+                    let delta = sub(self.walls.get(id).pos, ball.body.pos);
+                    let distance = norm(delta);
+                    if distance < range && distance > 0. {
+                        // linear falloff down to zero at range
+                        let intensity = strength*(1. - distance/range);
+                        force = add(force, mul(intensity/distance, delta));
+                    }
+                }
+            }
+        }
+        {
+            let ball = &mut self.players[i].ball;
+            ball.acc = force;
+
+            ball.vel[0] += dt*ball.acc[0];
+            ball.vel[1] += dt*ball.acc[1];
+
+            ball.spin -= dt*CFG.gameplay.damping*ball.spin;
+            ball.angle += dt*ball.spin;
+        }
+
+        // move until the next wall, bounce on it and go on with the rest of the tick
+        let mut remaining = dt;
+        for _ in 0..CFG.physics.max_bounces + 1 {
+            remaining -= self.sweep(i, remaining);
+            self.resolve_collisions(i, touched_switches);
+            if remaining <= 0. || self.results.is_some() {
+                break
+            }
+        }
+        self.update_hook(i, dt);
+        let speed = norm(self.players[i].ball.vel);
+        self.emit(Event::Speed { speed: speed });
+
+        let out = self.bounds.as_ref().map_or(false, |bounds| self.players[i].ball.body.collide(bounds).is_none());
+        if out || self.hazards.iter().any(|hazard| self.players[i].ball.body.collide(&hazard.body).is_some()) {
+            self.die(i);
+            // in a race only the ball goes back to start
+            return self.results.is_none() && self.players.len() > 1 && !tethered()
+        }
+        true
+    }
+    /// Move the ball along its velocity for at most time, it stops just inside the first
+    /// solid wall on its way so fast balls can't pass through thin walls.
+    /// Walls the ball already touches are left to the collision response.
+    /// Return the time the ball moved.
+    fn sweep(&mut self, i: usize, time: f64) -> f64 {
+        // how far the ball gets into the wall so that the collision is detected
+        const SKIN: f64 = 1e-6;
+
+        let ball = &self.players[i].ball;
+        let speed = norm(ball.vel);
+        if speed == 0. {
+            return time
+        }
+        let length = speed*time;
+        let direction = mul(1./speed, ball.vel);
+        let radius = ball.radius();
+        let start = ball.body.pos;
+        let end = add(start, mul(length, direction));
+        let area = Body {
+            pos: mul(0.5, add(start, end)),
+            shape: Shape::Rectangle((end[0] - start[0]).abs() + 2.*radius, (end[1] - start[1]).abs() + 2.*radius),
+        };
+        let hit = self.walls.get_on_body(&area).into_iter()
+            .filter(|&id| match self.kinds[id] {
+                Kind::Water | Kind::Pickup(_) | Kind::Gravity(_) | Kind::Goal => false,
+                Kind::OneWay(side) => dot(ball.vel, side) < 0.,
+                _ => true,
+            })
+            .filter_map(|id| self.walls.get(id).sweep(radius, start, direction, length))
+            .filter(|&distance| distance > 0.)
+            .fold(None, |min: Option<f64>, distance| Some(min.map_or(distance, |min| min.min(distance))));
+
+        let (distance, moved) = match hit {
+            Some(distance) => ((distance + SKIN).min(length), time*distance/length),
+            None => (length, time),
+        };
+        self.players[i].ball.body.pos = add(start, mul(distance, direction));
+        moved
+    }
+    /// Bounce on the walls the ball overlaps and trigger the ones it is in
+    fn resolve_collisions(&mut self, i: usize, touched_switches: &mut Vec<usize>) {
+        let body = self.players[i].ball.body.clone();
+        let vel = self.players[i].ball.vel;
+        let intensity = norm(vel);
 
         let mut collision = None;
-        for w in &self.walls.get_on_body(&self.ball) {
-            if let Some(c) = self.ball.collide(w) {
+        let mut contacts = vec!();
+        let mut in_water = false;
+        let mut pickups = vec!();
+        let mut gravity_triggers = vec!();
+        let mut goal = false;
+        // walls bounced on and the speed of the ball into each of them
+        let mut hits = vec!();
+        for id in self.walls.get_on_body(&body) {
+            if let Some(c) = body.collide(self.walls.get(id)) {
+                match self.kinds[id] {
+                    Kind::Water => {
+                        let center = Body {
+                            pos: body.pos,
+                            shape: Shape::Circle(0.),
+                        };
+                        in_water |= center.collide(self.walls.get(id)).is_some();
+                        continue
+                    },
+                    Kind::Pickup(_) => {
+                        pickups.push(id);
+                        continue
+                    },
+                    Kind::Gravity(_) => {
+                        gravity_triggers.push(id);
+                        continue
+                    },
+                    Kind::Goal => {
+                        goal = true;
+                        continue
+                    },
+                    Kind::Switch(_) => touched_switches.push(id),
+                    Kind::Spring(..) | Kind::Boost(_) | Kind::Conveyor(_) => contacts.push((id, c.clone())),
+                    Kind::Breakable(threshold) => if intensity > threshold {
+                        self.shatter(i, id);
+                        continue
+                    },
+                    // pass through unless the ball comes from the allowed side
+                    Kind::OneWay(side) => if dot(c.normal(), side) <= 0. || dot(vel, side) > 0. {
+                        continue
+                    },
+                    _ => (),
+                }
+                hits.push((id, -dot(vel, c.normal())));
                 collision = collision.map_or(Some(c.clone()), |mut collision: Collision| {collision.push(c); Some(collision)});
             }
         }
+        for id in pickups {
+            self.pick(i, id);
+        }
+        for &id in &gravity_triggers {
+            if !self.players[i].ball.gravity_triggers.contains(&id) {
+                match self.kinds[id] {
+                    Kind::Gravity(Gravity::To(angle)) => self.gravity_angle = angle,
+                    Kind::Gravity(Gravity::By(angle)) => self.gravity_angle += angle,
+                    _ => unreachable!(),
+                }
+            }
+        }
+        self.players[i].ball.gravity_triggers = gravity_triggers;
+        // the first player to reach the goal wins the race
+        if goal && self.results.is_none() {
+            self.finish(i);
+        }
+        if in_water != self.players[i].ball.in_water {
+            self.players[i].ball.in_water = in_water;
+            self.splash(i);
+        }
         // TODO check collision with gongs
         // and play sound for each
 
         if let Some(collision) = collision {
-            let intensity = (self.ball_vel[0].powi(2) + self.ball_vel[1].powi(2)).sqrt();
             let vol = if intensity >= CFG.audio.wall_max_intensity {
                 1.
             } else if intensity <= CFG.audio.wall_min_intensity {
@@ -124,42 +1004,657 @@ impl App {
                     (CFG.audio.wall_max_intensity - CFG.audio.wall_min_intensity)
             };
             self.audio.play_wall(vol as f32);
+            // grazing and resting contacts don't count as hits
+            for (id, impact) in hits {
+                if impact > CFG.gameplay.wall_hit_min_speed {
+                    self.emit(Event::WallHit { intensity: impact, wall: id });
+                }
+            }
 
-            self.ball.pos[0] += collision.dx;
-            self.ball.pos[1] += collision.dy;
+            let ball = &mut self.players[i].ball;
+            ball.body.pos[0] += collision.dx;
+            ball.body.pos[1] += collision.dy;
 
             let col_angle = angle([collision.dx, collision.dy]);
-            let vel_angle = angle(self.ball_vel);
+            let vel_angle = angle(ball.vel);
 
-            let norm = norm(self.ball_vel);
+            let norm = norm(ball.vel);
             let angle = col_angle + col_angle-vel_angle+PI;
 
-            self.ball_vel = from_polar([norm, angle]);
-            self.air_jump = true;
+            ball.vel = from_polar([norm, angle]);
+            ball.air_jump = true;
+
+            // friction at the contact point converts between sliding and spinning,
+            // the impulse that makes a disk roll without slipping is a third of the slip
+            let radius = ball.radius();
+            let tangent = collision.tangent();
+            let slip = dot(ball.vel, tangent) + radius*ball.spin;
+            let impulse = CFG.gameplay.friction*slip/3.;
+            ball.vel = sub(ball.vel, mul(impulse, tangent));
+            ball.spin -= 2.*impulse/radius;
+
+            for (id, c) in contacts {
+                match self.kinds[id] {
+                    Kind::Conveyor(speed) => {
+                        let tangent = c.tangent();
+                        let tangent_speed = dot(ball.vel, tangent);
+                        ball.vel = add(ball.vel, mul(CFG.gameplay.conveyor_grip*(speed - tangent_speed), tangent));
+                    },
+                    // still squashed from a previous contact
+                    _ if self.squashes.contains_key(&id) => (),
+                    Kind::Spring(direction, impulse) => {
+                        ball.vel = add(ball.vel, mul(impulse, direction));
+                        self.audio.play_spring();
+                        self.squashes.insert(id, Squash::new(c.normal()));
+                    },
+                    Kind::Boost(factor) => {
+                        let tangent = c.tangent();
+                        let tangent_speed = dot(ball.vel, tangent);
+                        ball.vel = add(ball.vel, mul((factor - 1.)*tangent_speed, tangent));
+                        self.audio.play_boost();
+                        self.squashes.insert(id, Squash::new(c.normal()));
+                    },
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+    fn pick(&mut self, i: usize, id: usize) {
+        self.walls.set_enabled(id, false);
+        let ball = &mut self.players[i].ball;
+        match self.kinds[id] {
+            Kind::Pickup(Pickup::Size(scale, duration)) => {
+                ball.set_radius(CFG.gameplay.ball_radius*scale);
+                ball.size_timer = Some(duration);
+            },
+            Kind::Pickup(Pickup::Life) => self.lives += 1,
+            _ => unreachable!(),
+        }
+    }
+    /// Droplets thrown up when entering or leaving water
+    fn splash(&mut self, i: usize) {
+        self.audio.play_splash();
+        let ball = &self.players[i].ball;
+        let n = CFG.graphics.splash_droplets;
+        let speed = norm(ball.vel).max(1.)*CFG.graphics.splash_speed;
+        for i in 0..n {
+            // spread between 45° and 135°
+            let k = (i as f64 + 0.5)/n as f64;
+            let angle = PI/4. + k*PI/2.;
+            self.particles.push(Particle {
+                pos: ball.body.pos,
+                vel: from_polar([speed*(0.6 + 0.4*(k*7.).sin().abs()), angle]),
+                radius: ball.radius()/4.,
+                timer: CFG.graphics.splash_timer,
+                duration: CFG.graphics.splash_timer,
+                color: CFG.graphics.splash_color,
+            });
+        }
+    }
+    /// Remove the wall until restart and replace it by debris flying away from the ball
+    fn shatter(&mut self, i: usize, id: usize) {
+        self.walls.set_enabled(id, false);
+        self.audio.play_shatter();
+
+        let ball = &self.players[i].ball;
+        let body = self.walls.get(id);
+        let (width, height) = body.shape.size();
+        let n = CFG.graphics.debris_per_side;
+        let radius = width.min(height)/n as f64/2.;
+        for i in 0..n {
+            for j in 0..n {
+                let pos = [
+                    body.pos[0] - width/2. + (i as f64 + 0.5)*width/n as f64,
+                    body.pos[1] - height/2. + (j as f64 + 0.5)*height/n as f64,
+                ];
+                let piece = Body {
+                    pos: pos,
+                    shape: Shape::Circle(0.),
+                };
+                if piece.collide(body).is_none() {
+                    continue
+                }
+                let away = sub(pos, ball.body.pos);
+                let vel = add(mul(CFG.graphics.debris_speed/norm(away).max(1.), away), mul(0.5, ball.vel));
+                self.particles.push(Particle {
+                    pos: pos,
+                    vel: vel,
+                    radius: radius,
+                    timer: CFG.graphics.debris_timer,
+                    duration: CFG.graphics.debris_timer,
+                    color: CFG.graphics.breakable_color,
+                });
+            }
+        }
+    }
+    /// Award medals, save progress and show results, multiplayer runs don't count for personal bests
+    fn finish(&mut self, i: usize) {
+        self.emit(Event::Goal);
+        let mut results = Results {
+            time: self.run_time,
+            jumps: self.jumps,
+            time_medal: MAP.time_medals.and_then(|thresholds| Medal::award(self.run_time, thresholds)),
+            jumps_medal: MAP.jumps_medals.and_then(|thresholds| Medal::award(self.jumps, thresholds)),
+            failed: false,
+            game_over: false,
+            new_best: false,
+            practice: self.practice,
+            height: None,
+            winner: if self.players.len() > 1 && !tethered() { Some(i) } else { None },
+        };
+        if self.practice || self.players.len() > 1 {
+            self.results = Some(results);
+            return
+        }
+        {
+            let level = self.progress.level(&MAP.name);
+            match CFG.gameplay.mode {
+                Mode::Normal => {
+                    results.new_best = level.best_time.map_or(true, |best| results.time < best);
+                    level.record(results.time, results.jumps, results.time_medal, results.jumps_medal);
+                },
+                Mode::FewestJumps => {
+                    results.new_best = level.record_fewest_jumps(results.jumps, results.time, self.replay.clone());
+                    level.record_medals(results.time_medal, results.jumps_medal);
+                },
+                // there is no goal in generated chunks
+                Mode::Endless => unreachable!(),
+            }
+        }
+        if let Err(e) = self.progress.save() {
+            println!("WARNING: {}", e);
+        }
+        self.results = Some(results);
+    }
+    /// Show results of a failed run, nothing is saved
+    fn fail(&mut self) {
+        self.results = Some(Results {
+            time: self.run_time,
+            jumps: self.jumps,
+            time_medal: None,
+            jumps_medal: None,
+            failed: true,
+            game_over: false,
+            new_best: false,
+            practice: self.practice,
+            height: None,
+            winner: None,
+        });
+    }
+    /// Generate chunks above the highest ball and drop the ones far below,
+    /// the run is over when all the balls fall below the loaded chunks
+    fn update_climb(&mut self) {
+        let chunk_height = CFG.endless.chunk_height;
+        let ball = self.players.iter()
+            .map(|player| player.ball.body.pos[1])
+            .fold(::std::f64::NEG_INFINITY, f64::max);
+        let fell = match self.climb {
+            Some(ref mut climb) => {
+                climb.height = climb.height.max(ball);
+                while (climb.next as f64)*chunk_height < ball + CFG.endless.generate_above {
+                    let mut ids = vec!();
+                    for body in endless::chunk(climb.next) {
+                        // ids of dropped chunks are reused
+                        let id = self.walls.insert(body);
+                        if id == self.kinds.len() {
+                            self.kinds.push(Kind::Wall);
+                            self.hookable.push(true);
+                        } else {
+                            self.kinds[id] = Kind::Wall;
+                            self.hookable[id] = true;
+                        }
+                        ids.push(id);
+                    }
+                    climb.chunks.push_back((climb.next, ids));
+                    climb.next += 1;
+                }
+                while climb.chunks.len() > 1 && (climb.chunks[0].0 + 1) as f64*chunk_height < climb.height - CFG.endless.drop_below {
+                    let (_, ids) = climb.chunks.pop_front().unwrap();
+                    for id in ids {
+                        self.walls.remove(id);
+                        self.squashes.remove(&id);
+                    }
+                }
+                ball < climb.bottom()
+            },
+            None => false,
+        };
+        if fell {
+            self.end_climb();
+        }
+    }
+    /// Save the height and show results
+    fn end_climb(&mut self) {
+        let height = self.climb.as_ref().map_or(0., |climb| climb.score());
+        let new_best = self.progress.level(&endless::level_name()).record_height(height);
+        if let Err(e) = self.progress.save() {
+            println!("WARNING: {}", e);
+        }
+        self.audio.play_shatter();
+        self.results = Some(Results {
+            time: self.run_time,
+            jumps: self.jumps,
+            time_medal: None,
+            jumps_medal: None,
+            failed: false,
+            game_over: false,
+            new_best: new_best,
+            practice: false,
+            height: Some(height),
+            winner: None,
+        });
+    }
+    /// Unlock and save the achievements satisfied by the event, practice runs don't count
+    fn emit(&mut self, event: Event) {
+        if self.practice {
+            return
+        }
+        let run = Run {
+            jumps: self.jumps,
+            time: self.run_time,
+        };
+        let mut unlocked = false;
+        for achievement in self.tracker.handle(&event, &run) {
+            if self.progress.achievements.insert(achievement.id.clone()) {
+                self.toasts.push_back(Toast {
+                    title: &achievement.title,
+                    timer: CFG.graphics.toast_timer,
+                });
+                unlocked = true;
+            }
+        }
+        if unlocked {
+            if let Err(e) = self.progress.save() {
+                println!("WARNING: {}", e);
+            }
+        }
+    }
+    /// Out of lives, jumping restarts the level with all the lives
+    fn game_over(&mut self) {
+        self.lives = CFG.gameplay.lives;
+        self.results = Some(Results {
+            time: self.run_time,
+            jumps: self.jumps,
+            time_medal: None,
+            jumps_medal: None,
+            failed: false,
+            game_over: true,
+            new_best: false,
+            practice: self.practice,
+            height: None,
+            winner: None,
+        });
+    }
+    /// Use up a life and restart the level, in a race only the ball goes back to start
+    fn die(&mut self, i: usize) {
+        self.emit(Event::Death);
+        self.audio.play_shatter();
+        if CFG.gameplay.lives > 0 {
+            self.lives -= 1;
+            if self.lives == 0 {
+                self.game_over();
+                return
+            }
+        }
+        if self.players.len() > 1 && !tethered() {
+            self.players[i].ball = Ball::new(start());
+            self.players[i].snap();
+        } else {
+            self.restart();
+        }
+    }
+    /// Put the ball back at start and restore the level
+    pub fn restart(&mut self) {
+        for player in &mut self.players {
+            player.ball = Ball::new(start());
+            player.snap();
+        }
+        self.gravity_angle = -PI/2.;
+        self.camera_angle = 0.;
+        self.run_time = 0.;
+        self.jumps = 0;
+        self.ticks = 0;
+        self.replay.clear();
+        self.results = None;
+        self.practice = false;
+        self.tracker = Tracker::new();
+        self.effects.clear();
+        self.particles.clear();
+        self.history.clear();
+        self.squashes.clear();
+        self.walls.enable_all();
+        if self.climb.is_some() {
+            self.walls = SpatialHashing::new(CFG.physics.unit, &vec!());
+            self.kinds.clear();
+            self.hookable.clear();
+            self.climb = Some(Climb::new());
+        } else {
+            self.hazards = MAP.hazards.clone();
+        }
+        for state in self.switches.values_mut() {
+            *state = SwitchState::default();
+        }
+    }
+    pub fn select_slot(&mut self, slot: usize) {
+        if slot < CFG.practice.slots {
+            self.slot = slot;
+        }
+    }
+    /// Save the run in the current slot
+    pub fn save_state(&mut self) {
+        // generated chunks are not saved and save states are for a single player
+        if self.results.is_some() || self.climb.is_some() || self.players.len() > 1 {
+            return
+        }
+        let ball = &self.players[0].ball;
+        let state = SaveState {
+            ball_pos: ball.body.pos,
+            ball_vel: ball.vel,
+            ball_acc: ball.acc,
+            ball_radius: ball.radius(),
+            ball_angle: ball.angle,
+            ball_spin: ball.spin,
+            air_jump: ball.air_jump,
+            size_timer: ball.size_timer,
+            in_water: ball.in_water,
+            gravity_angle: self.gravity_angle,
+            camera_angle: self.camera_angle,
+            run_time: self.run_time,
+            jumps: self.jumps,
+            ticks: self.ticks,
+            lives: if CFG.gameplay.lives > 0 { Some(self.lives) } else { None },
+            disabled: self.walls.disabled(),
+            gravity_triggers: ball.gravity_triggers.clone(),
+            replay: self.replay.clone(),
+            switches: self.switches.iter()
+                .map(|(&id, state)| save_state::Switch {
+                    id: id,
+                    pressed: state.pressed,
+                    used: state.used,
+                    open: state.open,
+                    timer: state.timer,
+                })
+                .collect(),
+            hazards: self.hazards.iter()
+                .map(|hazard| HazardState {
+                    pos: hazard.body.pos,
+                    next: match hazard.behavior {
+                        Behavior::Patrol { next, .. } => next,
+                        _ => 0,
+                    },
+                })
+                .collect(),
+            hook: ball.hook.as_ref().map(|hook| HookState {
+                wall: hook.wall,
+                anchor: hook.anchor,
+                length: hook.length,
+            }),
+        };
+        self.save_states.insert(&MAP.name, self.slot, state);
+        if CFG.practice.save_to_disk {
+            if let Err(e) = self.save_states.save() {
+                println!("WARNING: {}", e);
+            }
+        }
+    }
+    /// Restore the run saved in the current slot, the run won't count for personal bests
+    pub fn load_state(&mut self) {
+        if self.climb.is_some() || self.players.len() > 1 {
+            return
+        }
+        let state = match self.save_states.get(&MAP.name, self.slot) {
+            Some(state) => state.clone(),
+            None => return,
+        };
+        self.restart();
+        self.practice = true;
+
+        {
+            let ball = &mut self.players[0].ball;
+            ball.body.pos = state.ball_pos;
+            ball.vel = state.ball_vel;
+            ball.acc = state.ball_acc;
+            ball.set_radius(state.ball_radius);
+            ball.angle = state.ball_angle;
+            ball.spin = state.ball_spin;
+            ball.air_jump = state.air_jump;
+            ball.size_timer = state.size_timer;
+            ball.in_water = state.in_water;
+            ball.gravity_triggers = state.gravity_triggers;
+            ball.hook = state.hook.map(|hook| Hook {
+                wall: hook.wall,
+                anchor: hook.anchor,
+                length: hook.length,
+            });
+        }
+        if let Some(lives) = state.lives {
+            if CFG.gameplay.lives > 0 {
+                self.lives = lives;
+            }
+        }
+        self.players[0].snap();
+        self.gravity_angle = state.gravity_angle;
+        self.camera_angle = state.camera_angle;
+        self.run_time = state.run_time;
+        self.jumps = state.jumps;
+        self.ticks = state.ticks;
+        self.replay = state.replay;
+        for id in state.disabled {
+            self.walls.set_enabled(id, false);
+        }
+        for switch in state.switches {
+            if let Some(state) = self.switches.get_mut(&switch.id) {
+                state.pressed = switch.pressed;
+                state.used = switch.used;
+                state.open = switch.open;
+                state.timer = switch.timer;
+            }
+        }
+        for (hazard, state) in self.hazards.iter_mut().zip(state.hazards) {
+            hazard.body.pos = state.pos;
+            if let Behavior::Patrol { ref mut next, .. } = hazard.behavior {
+                *next = state.next;
+            }
+        }
+    }
+    fn update_switches(&mut self, dt: f64, touched: &[usize]) {
+        for (&id, state) in &mut self.switches {
+            let switch = match self.kinds[id] {
+                Kind::Switch(ref switch) => switch,
+                _ => unreachable!(),
+            };
+            let was_open = state.open;
+
+            if let Some(timer) = state.timer.take() {
+                if timer > dt {
+                    state.timer = Some(timer - dt);
+                } else {
+                    state.open = false;
+                }
+            }
+
+            let touching = touched.contains(&id);
+            if touching && !state.pressed && !(switch.once && state.used) {
+                state.used = true;
+                state.open = !state.open;
+                state.timer = if state.open { switch.timer } else { None };
+            }
+            state.pressed = touching;
+
+            if state.open != was_open {
+                for &door in &switch.doors {
+                    self.walls.set_enabled(door, !state.open);
+                }
+            }
         }
     }
-    pub fn do_unlimited_jump(&mut self) {
+    pub fn do_unlimited_jump(&mut self, i: usize) {
         self.audio.play_jump();
 
+        let jump_angle = self.players[i].jump_angle;
+        let ball = &mut self.players[i].ball;
         if CFG.gameplay.reset {
-            self.ball_vel = [0., 0.];
+            ball.vel = [0., 0.];
         }
-        self.ball_vel[0] += self.jump_angle.cos()*CFG.gameplay.impulse;
-        self.ball_vel[1] += self.jump_angle.sin()*CFG.gameplay.impulse;
+        let impulse = if ball.in_water {
+            CFG.gameplay.impulse*CFG.gameplay.water_impulse
+        } else {
+            CFG.gameplay.impulse
+        };
+        ball.vel[0] += jump_angle.cos()*impulse;
+        ball.vel[1] += jump_angle.sin()*impulse;
 
         self.effects.push(Effect {
-            pos: [self.ball.pos[0], self.ball.pos[1]],
-            angle: self.jump_angle,
+            pos: [ball.body.pos[0], ball.body.pos[1]],
+            angle: jump_angle,
             timer: CFG.graphics.effect_timer,
+            radius: ball.radius(),
         });
     }
-    pub fn do_jump(&mut self) {
-        if self.air_jump {
-            self.air_jump = false;
-            self.do_unlimited_jump()
+    pub fn do_jump(&mut self, i: usize) {
+        if i >= self.players.len() {
+            return
+        }
+        // jumping on results screen restarts
+        if self.results.is_some() {
+            self.restart();
+            return
         }
+        if CFG.gameplay.mode == Mode::FewestJumps {
+            if CFG.gameplay.jump_cap.map_or(false, |cap| self.jumps >= cap) {
+                self.fail();
+                return
+            }
+            self.jumps += 1;
+            self.replay.push(ReplayJump {
+                tick: self.ticks,
+                angle: self.players[i].jump_angle,
+            });
+        }
+        if self.players[i].ball.air_jump {
+            self.players[i].ball.air_jump = false;
+            if CFG.gameplay.mode != Mode::FewestJumps {
+                self.jumps += 1;
+            }
+            self.do_unlimited_jump(i);
+            self.emit(Event::Jump);
+        }
+    }
+    /// Attach the hook to the first hookable wall in the aim direction
+    pub fn fire_hook(&mut self, i: usize) {
+        if !CFG.hook.enabled || i >= self.players.len() || self.results.is_some() {
+            return
+        }
+        let origin = self.players[i].ball.body.pos;
+        let direction = from_polar([1., self.players[i].jump_angle]);
+        let hit = {
+            let kinds = &self.kinds;
+            let hookable = &self.hookable;
+            self.walls.raycast(origin, direction, CFG.hook.length, |id| hookable[id] && match kinds[id] {
+                // not solid
+                Kind::Water | Kind::Pickup(_) | Kind::Gravity(_) | Kind::Goal => false,
+                _ => true,
+            })
+        };
+        if let Some((wall, distance)) = hit {
+            self.players[i].ball.hook = Some(Hook {
+                wall: wall,
+                anchor: add(origin, mul(distance, direction)),
+                length: distance.max(CFG.hook.min_length),
+            });
+            self.audio.play_wall(0.5);
+        }
+    }
+    pub fn release_hook(&mut self, i: usize) {
+        if let Some(player) = self.players.get_mut(i) {
+            player.ball.hook = None;
+        }
+    }
+    /// 1 reels in, -1 reels out
+    pub fn set_reel(&mut self, i: usize, reel: f64) {
+        if let Some(player) = self.players.get_mut(i) {
+            player.reel = reel;
+        }
+    }
+    /// angle is on screen, it is rotated with the camera
+    pub fn set_jump_angle(&mut self, i: usize, angle: f64) {
+        if let Some(player) = self.players.get_mut(i) {
+            player.jump_angle = angle + self.camera_angle;
+        }
+    }
+}
+
+fn draw_line(frame: &mut graphics::Frame, a: [f64; 2], b: [f64; 2], thickness: f32, color: [f32; 4]) {
+    let delta = sub(b, a);
+    let transform = graphics::Transformation::identity()
+        .translate(((a[0] + b[0])/2.) as f32, ((a[1] + b[1])/2.) as f32)
+        .rotate(angle(delta) as f32)
+        .scale((norm(delta)/2.) as f32, thickness/2.);
+    frame.draw_quad(transform, Layer::World, color);
+}
+
+fn draw_body(frame: &mut graphics::Frame, body: &Body, color: [f32; 4]) {
+    let (x, y) = (body.pos[0] as f32, body.pos[1] as f32);
+    match body.shape {
+        Shape::Circle(radius) => frame.draw_circle(x, y, radius as f32, Layer::World, color),
+        Shape::Rectangle(width, height) => frame.draw_rectangle(x, y, width as f32, height as f32, Layer::World, color),
+        Shape::OrientedRectangle(width, height, angle) => {
+            let transform = graphics::Transformation::identity()
+                .translate(x, y)
+                .rotate(angle as f32)
+                .scale((width/2.) as f32, (height/2.) as f32);
+            frame.draw_quad(transform, Layer::World, color);
+        },
+        Shape::Polygon(ref vertices) => {
+            let vertices: Vec<_> = vertices.iter().map(|v| [v[0] as f32, v[1] as f32]).collect();
+            frame.draw_polygon(x, y, &vertices, Layer::World, color);
+        },
+        Shape::Compound(_) => for part in body.parts() {
+            draw_body(frame, &part, color);
+        },
     }
-    pub fn set_jump_angle(&mut self, angle: f64) {
-        self.jump_angle = angle;
+}
+
+fn medal_color(medal: Medal) -> [f32; 4] {
+    match medal {
+        Medal::Bronze => CFG.graphics.bronze_color,
+        Medal::Silver => CFG.graphics.silver_color,
+        Medal::Gold => CFG.graphics.gold_color,
     }
 }
+
+/// Rewinding is not allowed in competitive modes
+fn rewind_enabled() -> bool {
+    CFG.rewind.enabled && CFG.gameplay.mode == Mode::Normal
+}
+
+fn start() -> [f64; 2] {
+    if CFG.gameplay.mode == Mode::Endless {
+        endless::start()
+    } else {
+        MAP.start
+    }
+}
+
+/// The first two players are joined in co-op
+fn tethered() -> bool {
+    CFG.multiplayer.tether != Tether::None
+}
+
+/// Rectangle around the bodies extended by the out of bounds margin
+fn bounds(bodies: &[Body]) -> Option<Body> {
+    let margin = CFG.gameplay.out_of_bounds_margin;
+    bodies.iter()
+        .map(|body| {
+            let (width, height) = body.shape.size();
+            ([body.pos[0] - width/2., body.pos[1] - height/2.], [body.pos[0] + width/2., body.pos[1] + height/2.])
+        })
+        .fold(None, |bounds: Option<([f64; 2], [f64; 2])>, (min, max)| Some(match bounds {
+            Some((bmin, bmax)) => ([bmin[0].min(min[0]), bmin[1].min(min[1])], [bmax[0].max(max[0]), bmax[1].max(max[1])]),
+            None => (min, max),
+        }))
+        .map(|(min, max)| Body {
+            pos: mul(0.5, add(min, max)),
+            shape: Shape::Rectangle(max[0] - min[0] + 2.*margin, max[1] - min[1] + 2.*margin),
+        })
+}
@@ -64,10 +64,24 @@ fn read_snd_files() -> Result<(&'static [u8], &'static [u8]), Error> {
     Ok((wall, jump))
 }
 
+#[cfg(feature = "exclude_all")]
+fn decode(file: &FileType) -> Result<Decoder<io::Cursor<FileType>>, Error> {
+    Ok(Decoder::new(io::Cursor::new(file.clone()))?)
+}
+
+#[cfg(not(feature = "exclude_all"))]
+fn decode(file: &FileType) -> Result<Decoder<io::Cursor<FileType>>, Error> {
+    Ok(Decoder::new(io::Cursor::new(*file))?)
+}
+
 pub struct Audio {
     endpoint: rodio::Device,
     wall: Buffered<Amplify<Decoder<io::Cursor<FileType>>>>,
     jump: Buffered<Amplify<Decoder<io::Cursor<FileType>>>>,
+    spring: Buffered<Speed<Amplify<Decoder<io::Cursor<FileType>>>>>,
+    boost: Buffered<Speed<Amplify<Decoder<io::Cursor<FileType>>>>>,
+    shatter: Buffered<Speed<Amplify<Decoder<io::Cursor<FileType>>>>>,
+    splash: Buffered<Speed<Amplify<Decoder<io::Cursor<FileType>>>>>,
 }
 
 impl Audio {
@@ -75,12 +89,29 @@ impl Audio {
         let snds = read_snd_files()?;
         Ok(Audio {
             endpoint: rodio::default_output_device().ok_or(Error::NoEndpoint)?,
-            wall: Decoder::new(io::Cursor::new(snds.0))?
+            wall: decode(&snds.0)?
                 .amplify(CFG.audio.wall_volume)
                 .buffered(),
-            jump: Decoder::new(io::Cursor::new(snds.1))?
+            jump: decode(&snds.1)?
                 .amplify(CFG.audio.jump_volume)
                 .buffered(),
+            // spring, boost, shatter and splash are the jump and wall sounds played at another speed
+            spring: decode(&snds.1)?
+                .amplify(CFG.audio.spring_volume)
+                .speed(CFG.audio.spring_speed)
+                .buffered(),
+            boost: decode(&snds.0)?
+                .amplify(CFG.audio.boost_volume)
+                .speed(CFG.audio.boost_speed)
+                .buffered(),
+            shatter: decode(&snds.0)?
+                .amplify(CFG.audio.shatter_volume)
+                .speed(CFG.audio.shatter_speed)
+                .buffered(),
+            splash: decode(&snds.1)?
+                .amplify(CFG.audio.splash_volume)
+                .speed(CFG.audio.splash_speed)
+                .buffered(),
         })
     }
 
@@ -94,4 +125,20 @@ impl Audio {
             rodio::play_raw(&self.endpoint, source.convert_samples());
         }
     }
+
+    pub fn play_spring(&self) {
+        rodio::play_raw(&self.endpoint, self.spring.clone().convert_samples());
+    }
+
+    pub fn play_boost(&self) {
+        rodio::play_raw(&self.endpoint, self.boost.clone().convert_samples());
+    }
+
+    pub fn play_shatter(&self) {
+        rodio::play_raw(&self.endpoint, self.shatter.clone().convert_samples());
+    }
+
+    pub fn play_splash(&self) {
+        rodio::play_raw(&self.endpoint, self.splash.clone().convert_samples());
+    }
 }
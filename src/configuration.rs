@@ -12,11 +12,18 @@ pub struct Configuration {
     pub control: Control,
     pub physics: Physics,
     pub audio: Audio,
+    pub rewind: Rewind,
+    pub practice: Practice,
+    pub endless: Endless,
+    pub multiplayer: Multiplayer,
+    pub hook: Hook,
 }
 
 #[derive(Deserialize)]
 pub struct Control {
     pub mouse_sensibility: f64,
+    /// stick deflection below which gamepads don't aim
+    pub gamepad_dead_zone: f64,
 }
 /// Those setting are not taking into account for emscripten backend
 #[derive(Deserialize)]
@@ -24,18 +31,83 @@ pub struct Window {
     pub samples: u8,
     pub vsync: bool,
 }
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Normal,
+    /// every jump attempt counts and runs are ranked by jumps
+    FewestJumps,
+    /// climb generated chunks instead of the map, runs are ranked by height
+    Endless,
+}
 #[derive(Deserialize)]
 pub struct Gameplay {
+    pub mode: Mode,
+    /// in fewest jumps mode the run fails when jumping more than this
+    pub jump_cap: Option<u32>,
     pub gravity: f64,
     pub ball_radius: f64,
     pub damping: f64,
     pub impulse: f64,
     pub reset: bool,
+    /// how much of the conveyor speed is given to the ball on each contact
+    pub conveyor_grip: f64,
+    /// upward force in water relative to gravity
+    pub water_buoyancy: f64,
+    pub water_damping: f64,
+    /// multiply the jump impulse from inside water
+    pub water_impulse: f64,
+    /// from 0 for ice to 1 for a ball that always rolls without slipping
+    pub friction: f64,
+    /// 0 disables lives, a death then only restarts the level
+    pub lives: u32,
+    /// the ball dies this far outside of the map
+    pub out_of_bounds_margin: f64,
+    /// slower contacts with a wall don't count as hits for achievements
+    pub wall_hit_min_speed: f64,
 }
 #[derive(Deserialize)]
 pub struct Graphics {
     pub ball_color: [f32; 4],
+    /// dot showing the rotation of the balls
+    pub spin_marker_color: [f32; 4],
+    /// colors of the players after the first one
+    pub player_colors: Vec<[f32; 4]>,
+    pub tether_color: [f32; 4],
+    pub tether_thickness: f32,
+    pub hook_color: [f32; 4],
+    pub hook_thickness: f32,
     pub wall_color: [f32; 4],
+    pub door_color: [f32; 4],
+    pub open_door_color: [f32; 4],
+    pub switch_color: [f32; 4],
+    pub one_way_color: [f32; 4],
+    pub spring_color: [f32; 4],
+    pub boost_color: [f32; 4],
+    pub breakable_color: [f32; 4],
+    pub conveyor_color: [f32; 4],
+    pub conveyor_stripe_color: [f32; 4],
+    pub attractor_color: [f32; 4],
+    pub repulsor_color: [f32; 4],
+    pub attractor_range_color: [f32; 4],
+    pub water_color: [f32; 4],
+    pub splash_color: [f32; 4],
+    pub hazard_color: [f32; 4],
+    pub grow_color: [f32; 4],
+    pub shrink_color: [f32; 4],
+    pub life_color: [f32; 4],
+    pub gravity_color: [f32; 4],
+    pub gravity_arrow_color: [f32; 4],
+    pub goal_color: [f32; 4],
+    pub text_color: [f32; 4],
+    pub results_background_color: [f32; 4],
+    pub toast_color: [f32; 4],
+    pub toast_background_color: [f32; 4],
+    /// seconds an achievement toast stays on screen
+    pub toast_timer: f64,
+    pub bronze_color: [f32; 4],
+    pub silver_color: [f32; 4],
+    pub gold_color: [f32; 4],
     pub background_color: [f32; 4],
     pub cursor_color: [f32; 4],
     pub cursor_inner_radius: f32,
@@ -44,23 +116,111 @@ pub struct Graphics {
     pub effect_timer: f64,
     pub effect_color: [f32; 4],
     pub effect_thickness: f32,
+    pub squash_timer: f64,
+    pub squash_amount: f64,
+    pub debris_timer: f64,
+    pub debris_speed: f64,
+    pub debris_per_side: usize,
+    pub conveyor_stripe_width: f64,
+    pub conveyor_stripe_spacing: f64,
+    pub splash_timer: f64,
+    pub splash_speed: f64,
+    pub splash_droplets: usize,
 }
 #[derive(Deserialize)]
 pub struct Camera {
     pub zoom: f64,
+    /// rotate the view so that gravity points down
+    pub rotate: bool,
+    /// in radians per second
+    pub rotation_speed: f64,
+    /// space kept around the players when zooming out to frame them all
+    pub spread_margin: f64,
 }
 #[derive(Deserialize)]
 pub struct EventLoop {
+    /// updates per second of the simulation, independent of the frame rate
+    pub ups: u32,
     pub max_fps: u32,
 }
 #[derive(Deserialize)]
 pub struct Physics {
     pub unit: f64,
+    /// number of walls a ball can bounce on during a single update
+    pub max_bounces: u32,
+}
+/// Grappling hook fired in the aim direction
+#[derive(Deserialize)]
+pub struct Hook {
+    pub enabled: bool,
+    /// maximum length of the rope
+    pub length: f64,
+    pub min_length: f64,
+    /// in unit per second
+    pub reel_speed: f64,
+}
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tether {
+    None,
+    /// the distance can't exceed the length
+    Rope,
+    /// pulls the balls back to the length
+    Spring,
+}
+/// Players race on the same screen, each with its own input
+#[derive(Deserialize)]
+pub struct Multiplayer {
+    /// from 1 to 4
+    pub players: usize,
+    /// join the first two players for co-op, there are at least two players then
+    pub tether: Tether,
+    pub tether_length: f64,
+    pub tether_stiffness: f64,
+    pub tether_damping: f64,
+}
+/// Chunks are generated from the seed so a seed always gives the same climb
+#[derive(Deserialize)]
+pub struct Endless {
+    pub seed: u64,
+    pub width: f64,
+    pub chunk_height: f64,
+    pub wall_thickness: f64,
+    /// platforms per chunk
+    pub platforms: usize,
+    /// probability for a platform to be a circle
+    pub circle_ratio: f64,
+    /// chunks are generated up to this distance above the ball
+    pub generate_above: f64,
+    /// chunks are dropped below this distance under the highest position
+    pub drop_below: f64,
+}
+/// Runs that loaded a save state don't count for personal bests
+#[derive(Deserialize)]
+pub struct Practice {
+    pub slots: usize,
+    /// keep save states between sessions
+    pub save_to_disk: bool,
+}
+/// Rewind is only available in normal mode
+#[derive(Deserialize)]
+pub struct Rewind {
+    pub enabled: bool,
+    /// in seconds
+    pub duration: f64,
 }
 #[derive(Deserialize)]
 pub struct Audio {
     pub jump_volume: f32,
     pub wall_volume: f32,
+    pub spring_volume: f32,
+    pub spring_speed: f32,
+    pub boost_volume: f32,
+    pub boost_speed: f32,
+    pub shatter_volume: f32,
+    pub shatter_speed: f32,
+    pub splash_volume: f32,
+    pub splash_speed: f32,
 
     pub wall_max_intensity: f64,
     pub wall_min_intensity: f64,
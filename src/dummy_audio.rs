@@ -10,4 +10,16 @@ impl Audio {
 
     pub fn play_wall(&self, _vol: f32) {
     }
+
+    pub fn play_spring(&self) {
+    }
+
+    pub fn play_boost(&self) {
+    }
+
+    pub fn play_shatter(&self) {
+    }
+
+    pub fn play_splash(&self) {
+    }
 }
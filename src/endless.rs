@@ -0,0 +1,110 @@
+use configuration::CFG;
+use physics::{Body, Shape};
+use std::collections::VecDeque;
+
+/// Top of the floor of the first chunk
+pub const FLOOR: f64 = 0.;
+
+/// Deterministic pseudo random generator (splitmix64)
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+    /// in [0, 1)
+    pub fn float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64/(1u64 << 53) as f64
+    }
+    /// in [min, max)
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + self.float()*(max - min)
+    }
+}
+
+/// State of a run in endless mode
+pub struct Climb {
+    /// index and wall ids of loaded chunks, the lowest at the front
+    pub chunks: VecDeque<(u64, Vec<usize>)>,
+    /// index of the next chunk to generate
+    pub next: u64,
+    /// highest position of the ball since restart
+    pub height: f64,
+}
+
+impl Climb {
+    pub fn new() -> Climb {
+        Climb {
+            chunks: VecDeque::new(),
+            next: 0,
+            height: start()[1],
+        }
+    }
+    /// Height climbed since start
+    pub fn score(&self) -> f64 {
+        self.height - start()[1]
+    }
+    /// Bottom of the lowest loaded chunk, the run is over below it
+    pub fn bottom(&self) -> f64 {
+        self.chunks.front().map_or(FLOOR, |&(index, _)| index as f64*CFG.endless.chunk_height)
+    }
+}
+
+/// Where the ball starts in endless mode
+pub fn start() -> [f64; 2] {
+    [0., FLOOR + CFG.gameplay.ball_radius]
+}
+
+/// Progress of each seed is saved apart
+pub fn level_name() -> String {
+    format!("endless {}", CFG.endless.seed)
+}
+
+/// Bodies of the chunk: two side walls and platforms spread over its height,
+/// the first chunk also has a floor. It only depends on the seed and the index.
+pub fn chunk(index: u64) -> Vec<Body> {
+    let width = CFG.endless.width;
+    let height = CFG.endless.chunk_height;
+    let thickness = CFG.endless.wall_thickness;
+    let bottom = index as f64*height;
+    let mut rng = Rng::new(CFG.endless.seed ^ index.wrapping_mul(0xD1B54A32D192ED03));
+    let mut bodies = vec!();
+
+    for &side in &[-1., 1.] {
+        bodies.push(Body {
+            pos: [side*(width + thickness)/2., bottom + height/2.],
+            shape: Shape::Rectangle(thickness, height),
+        });
+    }
+    if index == 0 {
+        bodies.push(Body {
+            pos: [0., FLOOR - thickness/2.],
+            shape: Shape::Rectangle(width, thickness),
+        });
+    }
+
+    let n = CFG.endless.platforms;
+    for i in 0..n {
+        // keep some room above the floor for the start
+        let y = bottom + (i as f64 + rng.range(0.5, 1.))*height/n as f64;
+        let shape = if rng.float() < CFG.endless.circle_ratio {
+            Shape::Circle(rng.range(0.5, 1.5))
+        } else {
+            Shape::Rectangle(rng.range(2., 6.), rng.range(0.5, 1.))
+        };
+        let half_width = shape.size().0/2.;
+        let x = rng.range(-width/2. + half_width, width/2. - half_width);
+        bodies.push(Body {
+            pos: [x, y],
+            shape: shape,
+        });
+    }
+    bodies
+}
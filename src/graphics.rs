@@ -76,6 +76,8 @@ pub struct Graphics {
     quad_indices: index::NoIndices,
     circle_vertex_buffer: VertexBuffer<Vertex>,
     circle_indices: index::NoIndices,
+    triangle_vertex_buffer: VertexBuffer<Vertex>,
+    triangle_indices: index::NoIndices,
     program: Program,
 
     draw_parameters: DrawParameters<'static>,
@@ -157,6 +159,14 @@ impl Graphics {
 
         let circle_indices = index::NoIndices(index::PrimitiveType::TriangleFan);
 
+        // mapped on each triangle of a polygon
+        let triangle_vertex = vec![Vertex { position: [0., 0.] },
+                                   Vertex { position: [1., 0.] },
+                                   Vertex { position: [0., 1.] }];
+        let triangle_vertex_buffer = VertexBuffer::new(facade, &triangle_vertex)?;
+
+        let triangle_indices = index::NoIndices(index::PrimitiveType::TrianglesList);
+
         let vertex_shader_src = r#"
             #version 100
             attribute vec2 position;
@@ -199,6 +209,8 @@ impl Graphics {
             quad_indices: quad_indices,
             circle_vertex_buffer: circle_vertex_buffer,
             circle_indices: circle_indices,
+            triangle_vertex_buffer: triangle_vertex_buffer,
+            triangle_indices: triangle_indices,
             program: program,
 
             draw_parameters: draw_parameters,
@@ -218,14 +230,17 @@ pub struct Camera {
     pub x: f32,
     pub y: f32,
     pub zoom: f32,
+    /// counterclockwise angle of the view in radians
+    pub rotation: f32,
 }
 
 impl Camera {
-    pub fn new(x: f32, y: f32, zoom: f32) -> Self {
+    pub fn new(x: f32, y: f32, zoom: f32, rotation: f32) -> Self {
         Camera {
             x: x,
             y: y,
             zoom: zoom,
+            rotation: rotation,
         }
     }
 }
@@ -245,10 +260,13 @@ impl<'a> Frame<'a> {
             };
             let dx = -camera.x;
             let dy = -camera.y;
-            [[     kx,      0., 0., 0.],
-             [     0.,      ky, 0., 0.],
-             [     0.,      0., 1., 0.],
-             [kx * dx, ky * dy, 0., 1.]]
+            // the world is rotated by -rotation
+            let c = camera.rotation.cos();
+            let s = camera.rotation.sin();
+            [[                 kx * c,                 -ky * s, 0., 0.],
+             [                 kx * s,                  ky * c, 0., 0.],
+             [                     0.,                      0., 1., 0.],
+             [kx * (c * dx + s * dy), ky * (c * dy - s * dx), 0., 1.]]
         };
         let billboard_camera_matrix = {
             [[1.,    0., 0., 0.],
@@ -349,6 +367,119 @@ impl<'a> Frame<'a> {
                   &self.graphics.draw_parameters)
             .unwrap();
     }
+
+    /// Draw a convex polygon, vertices are relative to x, y
+    pub fn draw_polygon(&mut self, x: f32, y: f32, vertices: &[[f32; 2]], layer: Layer, color: [f32; 4]) {
+        // as a fan of triangles from the first vertex
+        for i in 1..vertices.len().saturating_sub(1) {
+            let origin = vertices[0];
+            let b = vertices[i];
+            let c = vertices[i + 1];
+            let trans = {
+                [[b[0] - origin[0], b[1] - origin[1], 0., 0.],
+                 [c[0] - origin[0], c[1] - origin[1], 0., 0.],
+                 [0., 0., 1., 0.],
+                 [x + origin[0], y + origin[1], layer.into(), 1.]]
+            };
+
+            let uniform = uniform!{
+                trans: trans,
+                camera: self.camera(layer),
+                color: color,
+            };
+
+            self.frame
+                .draw(&self.graphics.triangle_vertex_buffer,
+                      &self.graphics.triangle_indices,
+                      &self.graphics.program,
+                      &uniform,
+                      &self.graphics.draw_parameters)
+                .unwrap();
+        }
+    }
+
+    /// Draw text centered on x, y with a pixel font, size is the height of a character
+    pub fn draw_text(&mut self, text: &str, x: f32, y: f32, size: f32, layer: Layer, color: [f32; 4]) {
+        let pixel = size / GLYPH_HEIGHT as f32;
+        let advance = (GLYPH_WIDTH + 1) as f32 * pixel;
+        let count = text.chars().count() as f32;
+        let left = x - (count * advance - pixel) / 2.;
+        let top = y + size / 2.;
+
+        for (i, c) in text.chars().enumerate() {
+            let char_left = left + i as f32 * advance;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                let cy = top - (row as f32 + 0.5) * pixel;
+                // consecutive pixels of a row are drawn at once
+                let mut column = 0;
+                while column < GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        column += 1;
+                        continue;
+                    }
+                    let start = column;
+                    while column < GLYPH_WIDTH && bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        column += 1;
+                    }
+                    let width = (column - start) as f32 * pixel;
+                    let cx = char_left + start as f32 * pixel + width / 2.;
+                    self.draw_rectangle(cx, cy, width, pixel, layer, color);
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Rows of the character from top to bottom, the left pixel is the highest bit
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        _ => [0b111, 0b001, 0b011, 0b000, 0b010],
+    }
 }
 
 #[derive(Clone,Copy,PartialEq)]
@@ -0,0 +1,66 @@
+use math::*;
+use physics::Body;
+
+/// A moving body that kills the ball on contact
+#[derive(Clone)]
+pub struct Hazard {
+    pub body: Body,
+    pub speed: f64,
+    pub behavior: Behavior,
+}
+
+#[derive(Clone)]
+pub enum Behavior {
+    /// Go through points in loop
+    Patrol {
+        points: Vec<[f64; 2]>,
+        next: usize,
+    },
+    /// Turn around center, counterclockwise for a positive speed
+    Orbit {
+        center: [f64; 2],
+    },
+    /// Go toward the ball while it is in range of home, else go back home
+    Chase {
+        home: [f64; 2],
+        range: f64,
+    },
+}
+
+impl Hazard {
+    pub fn update(&mut self, dt: f64, ball: [f64; 2]) {
+        let step = self.speed*dt;
+        match self.behavior {
+            Behavior::Patrol { ref points, ref mut next } => {
+                let target = points[*next];
+                if move_toward(&mut self.body.pos, target, step) {
+                    *next = (*next + 1) % points.len();
+                }
+            },
+            Behavior::Orbit { center } => {
+                let polar = into_polar(sub(self.body.pos, center));
+                if polar[0] > 0. {
+                    let angle = polar[1] + step/polar[0];
+                    self.body.pos = add(center, from_polar([polar[0], angle]));
+                }
+            },
+            Behavior::Chase { home, range } => {
+                let target = if norm(sub(ball, home)) < range { ball } else { home };
+                move_toward(&mut self.body.pos, target, step);
+            },
+        }
+    }
+}
+
+/// Move pos of step toward target, return whereas target is reached
+fn move_toward(pos: &mut [f64; 2], target: [f64; 2], step: f64) -> bool {
+    let delta = sub(target, *pos);
+    let distance = norm(delta);
+    if distance <= step {
+        *pos = target;
+        true
+    } else {
+        *pos = add(*pos, mul(step/distance, delta));
+        false
+    }
+}
@@ -1,4 +1,5 @@
 extern crate fps_clock;
+extern crate time;
 #[macro_use] extern crate glium;
 #[macro_use] extern crate lazy_static;
 #[macro_use] extern crate serde_derive;
@@ -16,6 +17,12 @@ mod audio;
 mod audio;
 mod app;
 mod map;
+mod hazard;
+mod progress;
+mod save_state;
+mod toml_file;
+mod endless;
+mod achievements;
 mod physics;
 pub mod graphics;
 #[cfg(target_os = "emscripten")]
@@ -46,6 +53,9 @@ fn main() {
     safe_main().ok_or_exit();
 }
 
+/// Longer frames are shortened, the game slows down instead
+const MAX_FRAME_TIME: f64 = 0.25;
+
 fn safe_main() -> Result<(), String> {
     let mut events_loop = glutin::EventsLoop::new();
     let window_builder = glutin::WindowBuilder::new()
@@ -67,6 +77,17 @@ fn safe_main() -> Result<(), String> {
     let mut app = app::App::new(audio);
 
     let mut last_set_inner_size = (0, 0);
+    // direction keys held by each player
+    let mut held = [[false; 4]; 4];
+    // hook keys held by each player
+    let mut hook_held = [[false; 3]; 4];
+    // jump, hook, reel in and reel out buttons held on each gamepad
+    let mut pad_held = [[false; 4]; 4];
+    // player of each touch on the screen and whereas it holds the hook
+    let mut touches = ::std::collections::HashMap::new();
+    // the simulation advances by fixed steps, the time left is carried to the next frame
+    let step = 1.0 / CFG.event_loop.ups as f64;
+    let mut accumulator = 0.0;
     // return whereas main loop breaks
     set_main_loop(|dt| -> bool {
         {
@@ -83,13 +104,69 @@ fn safe_main() -> Result<(), String> {
             use glium::glutin::TouchPhase;
             match event {
                 WindowEvent { event: Closed, .. } => app.must_quit = true,
+                WindowEvent { event: KeyboardInput { input: glutin::KeyboardInput { state, virtual_keycode: Some(key), .. }, .. }, .. } => {
+                    let pressed = state == glutin::ElementState::Pressed;
+                    if let Some((player, direction)) = direction_key(key) {
+                        let repeat = held[player][direction] && pressed;
+                        held[player][direction] = pressed;
+                        // aim and jump toward the held directions
+                        let keys = held[player];
+                        let x = keys[3] as i32 - keys[1] as i32;
+                        let y = keys[0] as i32 - keys[2] as i32;
+                        if (x, y) != (0, 0) {
+                            app.set_jump_angle(player, (y as f64).atan2(x as f64));
+                            if pressed && !repeat {
+                                app.do_jump(player);
+                            }
+                        }
+                    }
+                    if let Some((player, action)) = hook_key(key) {
+                        let repeat = hook_held[player][action] && pressed;
+                        hook_held[player][action] = pressed;
+                        let keys = hook_held[player];
+                        match action {
+                            0 if repeat => (),
+                            0 if pressed => app.fire_hook(player),
+                            0 => app.release_hook(player),
+                            _ => app.set_reel(player, keys[1] as i32 as f64 - keys[2] as i32 as f64),
+                        }
+                    }
+                    match key {
+                        glutin::VirtualKeyCode::R if pressed => app.restart(),
+                        glutin::VirtualKeyCode::Back => app.rewinding = pressed,
+                        glutin::VirtualKeyCode::F5 if pressed => app.save_state(),
+                        glutin::VirtualKeyCode::F9 if pressed => app.load_state(),
+                        _ if pressed => if let Some(slot) = slot_key(key) {
+                            app.select_slot(slot);
+                        },
+                        _ => (),
+                    }
+                },
                 WindowEvent { event: Touch(touch), .. } => {
-                    if touch.phase == TouchPhase::Started {
-                        let (w, h) = window.gl_window().get_inner_size().unwrap();
-                        let x = touch.location.0 - (w/2) as f64;
-                        let y = - (touch.location.1 - (h/2) as f64);
-                        app.set_jump_angle(y.atan2(x) + ::std::f64::consts::PI);
-                        app.do_jump();
+                    match touch.phase {
+                        TouchPhase::Started => {
+                            // each player has a vertical strip of the screen
+                            let (w, h) = window.gl_window().get_inner_size().unwrap();
+                            let strip = w as f64/app.player_count() as f64;
+                            let player = ((touch.location.0/strip) as usize).min(app.player_count() - 1);
+                            // a second finger in the strip holds the hook
+                            let hook = touches.values().any(|&(other, _)| other == player);
+                            touches.insert(touch.id, (player, hook));
+                            if hook {
+                                app.fire_hook(player);
+                            } else {
+                                let x = touch.location.0 - (player as f64 + 0.5)*strip;
+                                let y = - (touch.location.1 - (h/2) as f64);
+                                app.set_jump_angle(player, y.atan2(x) + ::std::f64::consts::PI);
+                                app.do_jump(player);
+                            }
+                        },
+                        TouchPhase::Ended | TouchPhase::Cancelled => {
+                            if let Some((player, true)) = touches.remove(&touch.id) {
+                                app.release_hook(player);
+                            }
+                        },
+                        TouchPhase::Moved => (),
                     }
                 },
                 WindowEvent { event: Refresh, .. } => {
@@ -106,7 +183,36 @@ fn safe_main() -> Result<(), String> {
             }
         });
 
-        app.update(dt);
+        for player in 0..app.player_count().min(pad_held.len()) {
+            let pad = match gamepad(player) {
+                Some(pad) => pad,
+                None => continue,
+            };
+            if pad.stick[0].hypot(pad.stick[1]) > CFG.control.gamepad_dead_zone {
+                app.set_jump_angle(player, pad.stick[1].atan2(pad.stick[0]));
+            }
+            let held = pad_held[player];
+            if pad.buttons[0] && !held[0] {
+                app.do_jump(player);
+            }
+            if pad.buttons[1] && !held[1] {
+                app.fire_hook(player);
+            } else if !pad.buttons[1] && held[1] {
+                app.release_hook(player);
+            }
+            if pad.buttons[2] != held[2] || pad.buttons[3] != held[3] {
+                app.set_reel(player, pad.buttons[2] as i32 as f64 - pad.buttons[3] as i32 as f64);
+            }
+            pad_held[player] = pad.buttons;
+        }
+
+        // if frames are too long then slow down the game rather than update a lot
+        accumulator += dt.min(MAX_FRAME_TIME);
+        while accumulator >= step {
+            app.update(step);
+            accumulator -= step;
+        }
+        app.interpolation = accumulator / step;
 
         let mut target = window.draw();
         {
@@ -123,10 +229,98 @@ fn safe_main() -> Result<(), String> {
     Ok(())
 }
 
+/// Player and direction (up, left, down, right) of a key
+fn direction_key(key: glutin::VirtualKeyCode) -> Option<(usize, usize)> {
+    use glutin::VirtualKeyCode::*;
+    let players = [
+        [W, A, S, D],
+        [Up, Left, Down, Right],
+        [I, J, K, L],
+        [Numpad8, Numpad4, Numpad5, Numpad6],
+    ];
+    for (player, keys) in players.iter().enumerate() {
+        if let Some(direction) = keys.iter().position(|&k| k == key) {
+            return Some((player, direction))
+        }
+    }
+    None
+}
+
+/// Player and action (fire, reel in, reel out) of a hook key
+fn hook_key(key: glutin::VirtualKeyCode) -> Option<(usize, usize)> {
+    use glutin::VirtualKeyCode::*;
+    let players = [
+        [Space, Q, E],
+        [Return, RShift, RControl],
+        [U, O, P],
+        [Numpad0, Numpad7, Numpad9],
+    ];
+    for (player, keys) in players.iter().enumerate() {
+        if let Some(action) = keys.iter().position(|&k| k == key) {
+            return Some((player, action))
+        }
+    }
+    None
+}
+
+struct Gamepad {
+    /// left stick, y goes up
+    stick: [f64; 2],
+    /// jump, hook, reel in, reel out
+    buttons: [bool; 4],
+}
+
+/// State of the gamepad of the player from the browser Gamepad API
+fn gamepad(index: usize) -> Option<Gamepad> {
+    let index = index as u32;
+    let connected: bool = js!{
+        var pads = navigator.getGamepads ? navigator.getGamepads() : [];
+        return !!pads[@{index}];
+    }.try_into().unwrap();
+    if !connected {
+        return None
+    }
+    let x: f64 = js!{ return navigator.getGamepads()[@{index}].axes[0] || 0; }.try_into().unwrap();
+    let y: f64 = js!{ return navigator.getGamepads()[@{index}].axes[1] || 0; }.try_into().unwrap();
+    let mut buttons = [false; 4];
+    // standard mapping: A, B, LB, RB
+    for (button, &id) in buttons.iter_mut().zip(&[0u32, 1, 4, 5]) {
+        *button = js!{
+            var button = navigator.getGamepads()[@{index}].buttons[@{id}];
+            return !!button && button.pressed;
+        }.try_into().unwrap();
+    }
+    Some(Gamepad {
+        stick: [x, -y],
+        buttons: buttons,
+    })
+}
+
+/// Number keys select practice slots
+fn slot_key(key: glutin::VirtualKeyCode) -> Option<usize> {
+    use glutin::VirtualKeyCode::*;
+    match key {
+        Key1 => Some(0),
+        Key2 => Some(1),
+        Key3 => Some(2),
+        Key4 => Some(3),
+        Key5 => Some(4),
+        Key6 => Some(5),
+        Key7 => Some(6),
+        Key8 => Some(7),
+        Key9 => Some(8),
+        _ => None,
+    }
+}
+
+/// The main loop is given the time elapsed since its previous call
 #[cfg(target_os = "emscripten")]
 fn set_main_loop<F: FnMut(f64) -> bool>(mut main_loop: F) {
-    let dt = 1.0 / 60f64;
+    let mut last = time::precise_time_s();
     emscripten::set_main_loop_callback(|| {
+        let now = time::precise_time_s();
+        let dt = now - last;
+        last = now;
         if main_loop(dt) {
             emscripten::cancel_main_loop();
         }
@@ -137,10 +331,12 @@ fn set_main_loop<F: FnMut(f64) -> bool>(mut main_loop: F) {
 // as long as the main loop doesn't end
 #[cfg(all(not(target_os = "emscripten")))]
 fn set_main_loop<F: FnMut(f64) -> bool>(mut main_loop: F) {
-    // If running out of time then slow down the game
     let mut fps_clock = fps_clock::FpsClock::new(CFG.event_loop.max_fps);
-    let dt = 1.0 / CFG.event_loop.max_fps as f64;
+    let mut last = time::precise_time_s();
     loop {
+        let now = time::precise_time_s();
+        let dt = now - last;
+        last = now;
         if main_loop(dt) {
             break
         }
@@ -5,12 +5,90 @@ use self::svgparser::ElementId;
 use self::svgparser::AttributeId;
 use self::svgparser::svg::ElementEnd;
 use physics::{Body, Shape};
+use hazard::{Hazard, Behavior};
 use OkOrExit;
 use self::svgparser::xmlparser::FromSpan;
+use std::collections::HashMap;
 
 pub struct Map {
     pub bodies: Vec<Body>,
+    /// kinds[i] is the kind of bodies[i]
+    pub kinds: Vec<Kind>,
+    /// moving bodies defined with `airjump:hazard`
+    pub hazards: Vec<Hazard>,
+    /// `airjump:hookable="false"` on a body: the grappling hook can't attach to it,
+    /// on the svg root it changes the default so only bodies with `hookable="true"` are
+    pub hookable: Vec<bool>,
     pub start: [f64; 2],
+    /// `airjump:name` on the svg root, used to save progress
+    pub name: String,
+    /// `airjump:time="bronze silver gold"` on the svg root: maximum times in seconds
+    pub time_medals: Option<[f64; 3]>,
+    /// `airjump:jumps="bronze silver gold"` on the svg root: maximum numbers of jumps
+    pub jumps_medals: Option<[u32; 3]>,
+}
+
+/// The behavior of a body, set with attributes of the `airjump` namespace
+/// on the svg element (e.g. `airjump:door="red"`)
+#[derive(Clone)]
+pub enum Kind {
+    Wall,
+    /// `door="label"`: solid until a switch with the same label opens it
+    Door,
+    /// `switch="label"`: opens or closes the doors with the same label when hit
+    Switch(Switch),
+    /// `one_way="side"`: only collide with a ball coming from this side,
+    /// side is `up`, `down`, `left`, `right` or an angle in degrees
+    OneWay([f64; 2]),
+    /// `spring="impulse"`: add the impulse toward `direction` on contact,
+    /// direction is given like for one way and defaults to `up`
+    Spring([f64; 2], f64),
+    /// `boost="factor"`: multiply the speed of the ball along the surface on contact
+    Boost(f64),
+    /// `breakable="intensity"`: shatter when hit faster than intensity
+    Breakable(f64),
+    /// `conveyor="speed"`: drag the ball along the surface, clockwise for
+    /// a positive speed
+    Conveyor(f64),
+    /// `attractor="strength"`: pull the ball toward the center when closer
+    /// than `range`, a negative strength pushes it away
+    Attractor(f64, f64),
+    /// `water="true"`: not solid, the ball floats and is slowed down inside
+    Water,
+    /// `pickup="type"`: not solid, consumed on contact until restart
+    Pickup(Pickup),
+    /// not solid, change gravity when the ball enters
+    Gravity(Gravity),
+    /// `goal="true"`: not solid, the level is completed when the ball enters
+    Goal,
+}
+
+#[derive(Clone)]
+pub enum Gravity {
+    /// `gravity="direction"`: make gravity point to direction,
+    /// direction is given like for one way or with diagonals like `up-left`
+    To(f64),
+    /// `gravity_rotate="degrees"`: rotate gravity counterclockwise
+    By(f64),
+}
+
+#[derive(Clone)]
+pub enum Pickup {
+    /// `pickup="size"`: multiply the ball radius by `scale` during `duration` seconds
+    Size(f64, f64),
+    /// `pickup="life"`: one more life
+    Life,
+}
+
+#[derive(Clone)]
+pub struct Switch {
+    /// ids of the doors in bodies
+    pub doors: Vec<usize>,
+    /// `mode="once"`: the switch can only be activated one time,
+    /// `mode="toggle"` (default): each hit opens or closes the doors
+    pub once: bool,
+    /// `timer="seconds"`: doors close again after this duration
+    pub timer: Option<f64>,
 }
 
 const MAP_FILE: &'static str = "map.svg";
@@ -19,6 +97,8 @@ enum Error {
     Io(::std::io::Error),
     Svg(svgparser::xmlparser::Error),
     ParseFloat(::std::num::ParseFloatError),
+    Attribute(String, String),
+    Transform(String),
 }
 impl From<::std::io::Error> for Error {
     fn from(err: ::std::io::Error) -> Error {
@@ -42,6 +122,8 @@ impl ::std::fmt::Display for Error {
             Io(ref e) => write!(fmt, "file `{}`: io error: {}", MAP_FILE, e),
             Svg(ref e) => write!(fmt, "file `{}`: svg parser error: {}", MAP_FILE, e),
             ParseFloat(ref e) => write!(fmt, "file `{}`: svg parser float error: {}", MAP_FILE, e),
+            Attribute(ref name, ref value) => write!(fmt, "file `{}`: invalid value `{}` for attribute `airjump:{}`", MAP_FILE, value, name),
+            Transform(ref value) => write!(fmt, "file `{}`: invalid transform `{}`", MAP_FILE, value),
         }
     }
 }
@@ -60,48 +142,585 @@ fn read_map_file() -> Result<&'static str, Error> {
     Ok(include_str!("../map.svg"))
 }
 
+/// The unit vector of a direction name or of an angle in degrees
+/// counterclockwise from the right
+fn direction(attribute: &str, value: &str) -> Result<[f64; 2], Error> {
+    use std::f64::consts::FRAC_1_SQRT_2;
+    Ok(match value {
+        "up" => [0., 1.],
+        "down" => [0., -1.],
+        "left" => [-1., 0.],
+        "right" => [1., 0.],
+        "up-left" => [-FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+        "up-right" => [FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+        "down-left" => [-FRAC_1_SQRT_2, -FRAC_1_SQRT_2],
+        "down-right" => [FRAC_1_SQRT_2, -FRAC_1_SQRT_2],
+        _ => {
+            let angle = value.parse::<f64>()
+                .map_err(|_| Error::Attribute(attribute.into(), value.into()))?
+                .to_radians();
+            [angle.cos(), angle.sin()]
+        },
+    })
+}
+
+/// Parse a point in svg coordinates: `x,y`
+fn point(attribute: &str, value: &str) -> Result<[f64; 2], Error> {
+    let mut coordinates = value.split(',');
+    match (coordinates.next(), coordinates.next(), coordinates.next()) {
+        (Some(x), Some(y), None) => Ok([x.trim().parse()?, -y.trim().parse::<f64>()?]),
+        _ => Err(Error::Attribute(attribute.into(), value.into())),
+    }
+}
+
+/// Parse a required float attribute
+fn float(attributes: &HashMap<String, &str>, attribute: &str) -> Result<f64, Error> {
+    match attributes.get(attribute) {
+        Some(value) => Ok(value.parse()?),
+        None => Err(Error::Attribute(attribute.into(), String::new())),
+    }
+}
+
+/// Parse `true` or `false`
+fn boolean(attribute: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::Attribute(attribute.into(), value.into())),
+    }
+}
+
+/// Parse the three medal thresholds: `bronze silver gold`
+fn thresholds<T: ::std::str::FromStr>(attribute: &str, value: &str) -> Result<[T; 3], Error> {
+    let values = value.split_whitespace()
+        .map(|v| v.parse::<T>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::Attribute(attribute.into(), value.into()))?;
+    let mut values = values.into_iter();
+    match (values.next(), values.next(), values.next(), values.next()) {
+        (Some(bronze), Some(silver), Some(gold), None) => Ok([bronze, silver, gold]),
+        _ => Err(Error::Attribute(attribute.into(), value.into())),
+    }
+}
+
+/// Parse a svg transform list into the matrix `[a, b, c, d, e, f]` that maps
+/// `(x, y)` to `(a*x + c*y + e, b*x + d*y + f)`, supports matrix, translate,
+/// scale, rotate, skewX and skewY
+fn transform(value: &str) -> Result<[f64; 6], Error> {
+    let invalid = || Error::Transform(value.into());
+    let mut matrix = [1., 0., 0., 1., 0., 0.];
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        // transforms may be separated by a comma
+        if rest.starts_with(',') {
+            rest = rest[1..].trim();
+            continue
+        }
+        let open = rest.find('(').ok_or_else(&invalid)?;
+        let close = rest.find(')').ok_or_else(&invalid)?;
+        if close < open {
+            return Err(invalid())
+        }
+        let name = rest[..open].trim();
+        let args = rest[open + 1..close]
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| arg.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        let next = match (name, args.len()) {
+            ("matrix", 6) => [args[0], args[1], args[2], args[3], args[4], args[5]],
+            ("translate", 1) => [1., 0., 0., 1., args[0], 0.],
+            ("translate", 2) => [1., 0., 0., 1., args[0], args[1]],
+            ("scale", 1) => [args[0], 0., 0., args[0], 0., 0.],
+            ("scale", 2) => [args[0], 0., 0., args[1], 0., 0.],
+            ("rotate", 1) | ("rotate", 3) => {
+                let (s, c) = args[0].to_radians().sin_cos();
+                let (cx, cy) = if args.len() == 3 { (args[1], args[2]) } else { (0., 0.) };
+                // rotate around the center: translate(cx, cy) rotate(angle) translate(-cx, -cy)
+                [c, s, -s, c, cx - c*cx + s*cy, cy - s*cx - c*cy]
+            },
+            ("skewX", 1) => [1., 0., args[0].to_radians().tan(), 1., 0., 0.],
+            ("skewY", 1) => [1., args[0].to_radians().tan(), 0., 1., 0., 0.],
+            _ => return Err(invalid()),
+        };
+        matrix = compose(matrix, next);
+        rest = rest[close + 1..].trim();
+    }
+    Ok(matrix)
+}
+
+/// Matrix of the transform `outer` applied after `inner`
+fn compose(outer: [f64; 6], inner: [f64; 6]) -> [f64; 6] {
+    [
+        outer[0]*inner[0] + outer[2]*inner[1],
+        outer[1]*inner[0] + outer[3]*inner[1],
+        outer[0]*inner[2] + outer[2]*inner[3],
+        outer[1]*inner[2] + outer[3]*inner[3],
+        outer[0]*inner[4] + outer[2]*inner[5] + outer[4],
+        outer[1]*inner[4] + outer[3]*inner[5] + outer[5],
+    ]
+}
+
+/// Apply a svg transform matrix to a point in game coordinates, the y axis of svg goes down
+fn transformed_point(point: [f64; 2], matrix: [f64; 6]) -> [f64; 2] {
+    [
+        matrix[0]*point[0] - matrix[2]*point[1] + matrix[4],
+        -matrix[1]*point[0] + matrix[3]*point[1] - matrix[5],
+    ]
+}
+
+/// Mean factor by which a svg transform matrix scales lengths
+fn transformed_scale(matrix: [f64; 6]) -> f64 {
+    (matrix[0]*matrix[3] - matrix[1]*matrix[2]).abs().sqrt()
+}
+
+/// Apply a svg transform matrix to a body.
+/// Skew is ignored and circles are scaled by the mean factor.
+fn transformed(body: Body, matrix: [f64; 6]) -> Body {
+    let pos = transformed_point(body.pos, matrix);
+    let scale_x = (matrix[0].powi(2) + matrix[1].powi(2)).sqrt();
+    let scale_y = (matrix[2].powi(2) + matrix[3].powi(2)).sqrt();
+    // counterclockwise in the game
+    let angle = (-matrix[1]).atan2(matrix[0]);
+    let shape = match body.shape {
+        Shape::Circle(r) => Shape::Circle(r*transformed_scale(matrix)),
+        Shape::Rectangle(w, h) => rectangle(w*scale_x, h*scale_y, angle),
+        Shape::OrientedRectangle(w, h, base) => rectangle(w*scale_x, h*scale_y, base + angle),
+        Shape::Polygon(ref vertices) => {
+            let mut vertices: Vec<_> = vertices.iter()
+                .map(|v| [matrix[0]*v[0] - matrix[2]*v[1], -matrix[1]*v[0] + matrix[3]*v[1]])
+                .collect();
+            // a mirror turns the vertices clockwise
+            if matrix[0]*matrix[3] - matrix[1]*matrix[2] < 0. {
+                vertices.reverse();
+            }
+            Shape::Polygon(vertices)
+        },
+        Shape::Compound(ref parts) => {
+            let linear = [matrix[0], matrix[1], matrix[2], matrix[3], 0., 0.];
+            Shape::Compound(parts.iter().map(|part| transformed(part.clone(), linear)).collect())
+        },
+    };
+    Body {
+        pos: pos,
+        shape: shape,
+    }
+}
+
+/// Axis aligned rectangles are kept when possible as they are cheaper
+fn rectangle(width: f64, height: f64, angle: f64) -> Shape {
+    use std::f64::EPSILON;
+    if angle.sin().abs() < EPSILON.sqrt() {
+        Shape::Rectangle(width, height)
+    } else if angle.cos().abs() < EPSILON.sqrt() {
+        Shape::Rectangle(height, width)
+    } else {
+        Shape::OrientedRectangle(width, height, angle)
+    }
+}
+
+/// Parse the points of a polygon in svg coordinates: `x1,y1 x2,y2 ...`,
+/// repeated points are dropped, as is a last point closing the polygon on the first
+fn points(value: &str) -> Result<Vec<[f64; 2]>, Error> {
+    let coordinates = value.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|c| !c.is_empty())
+        .map(|c| c.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut points: Vec<[f64; 2]> = coordinates.chunks(2)
+        .filter(|c| c.len() == 2)
+        .map(|c| [c[0], -c[1]])
+        .collect();
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    Ok(points)
+}
+
+/// twice the area of the triangle, positive if it is counterclockwise
+fn cross(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    (b[0] - a[0])*(c[1] - a[1]) - (b[1] - a[1])*(c[0] - a[0])
+}
+
+/// Repeated vertices are ignored
+fn is_convex(points: &[[f64; 2]], piece: &[usize]) -> bool {
+    let n = piece.len();
+    let piece: Vec<_> = (0..n)
+        .filter(|&i| points[piece[i]] != points[piece[(i + 1) % n]])
+        .map(|i| piece[i])
+        .collect();
+    let n = piece.len();
+    (0..n).all(|i| cross(points[piece[i]], points[piece[(i + 1) % n]], points[piece[(i + 2) % n]]) >= 0.)
+}
+
+/// Split a simple polygon into convex polygons: triangulate it by clipping ears
+/// then merge neighbouring pieces while they stay convex
+fn convex_pieces(mut points: Vec<[f64; 2]>) -> Vec<Vec<[f64; 2]>> {
+    let n = points.len();
+    let area: f64 = (0..n).map(|i| cross([0., 0.], points[i], points[(i + 1) % n])).sum();
+    if area < 0. {
+        points.reverse();
+    }
+    let all: Vec<usize> = (0..n).collect();
+    if is_convex(&points, &all) {
+        return vec!(points)
+    }
+
+    let mut pieces = vec!();
+    let mut remaining = all;
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = (remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]);
+            cross(points[a], points[b], points[c]) > 0. && remaining.iter()
+                .filter(|&&j| j != a && j != b && j != c)
+                .all(|&j| {
+                    let p = points[j];
+                    cross(points[a], points[b], p) < 0. || cross(points[b], points[c], p) < 0. || cross(points[c], points[a], p) < 0.
+                })
+        });
+        match ear {
+            Some(i) => {
+                pieces.push(vec!(remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]));
+                remaining.remove(i);
+            },
+            None => {
+                println!("WARGNING: svg map polygon is not simple");
+                break
+            },
+        }
+    }
+    if remaining.len() == 3 {
+        pieces.push(remaining);
+    }
+
+    'merge: loop {
+        for a in 0..pieces.len() {
+            for b in a + 1..pieces.len() {
+                if let Some(merged) = merge(&points, &pieces[a], &pieces[b]) {
+                    pieces[a] = merged;
+                    pieces.remove(b);
+                    continue 'merge
+                }
+            }
+        }
+        break
+    }
+
+    pieces.into_iter()
+        .map(|piece| piece.into_iter().map(|i| points[i]).collect())
+        .collect()
+}
+
+/// The union of two pieces sharing an edge if it is convex
+fn merge(points: &[[f64; 2]], a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let (n, m) = (a.len(), b.len());
+    for i in 0..n {
+        let (start, end) = (a[i], a[(i + 1) % n]);
+        // the shared edge goes the other way in b
+        if let Some(j) = (0..m).find(|&j| b[j] == end && b[(j + 1) % m] == start) {
+            let mut merged: Vec<usize> = (0..n).map(|k| a[(i + 1 + k) % n]).collect();
+            merged.extend((0..m - 2).map(|k| b[(j + 2 + k) % m]));
+            if is_convex(points, &merged) {
+                return Some(merged)
+            }
+        }
+    }
+    None
+}
+
+/// Collects bodies and their kinds, labels are resolved at the end
+struct Builder {
+    bodies: Vec<Body>,
+    kinds: Vec<Kind>,
+    hazards: Vec<Hazard>,
+    /// None uses the default of the map
+    hookable: Vec<Option<bool>>,
+    doors: Vec<(String, usize)>,
+    switches: Vec<(String, usize)>,
+}
+
+impl Builder {
+    fn new() -> Builder {
+        Builder {
+            bodies: vec!(),
+            kinds: vec!(),
+            hazards: vec!(),
+            hookable: vec!(),
+            doors: vec!(),
+            switches: vec!(),
+        }
+    }
+
+    /// The body is already transformed by matrix, the transform of its element and groups
+    fn push(&mut self, body: Body, matrix: [f64; 6], attributes: &HashMap<String, &str>) -> Result<(), Error> {
+        if let Some(behavior) = attributes.get("hazard") {
+            return self.push_hazard(body, matrix, behavior, attributes);
+        }
+
+        let id = self.bodies.len();
+        let kind = if let Some(label) = attributes.get("door") {
+            self.doors.push((label.to_string(), id));
+            Kind::Door
+        } else if let Some(label) = attributes.get("switch") {
+            self.switches.push((label.to_string(), id));
+            let once = match attributes.get("mode") {
+                None | Some(&"toggle") => false,
+                Some(&"once") => true,
+                Some(mode) => return Err(Error::Attribute("mode".into(), mode.to_string())),
+            };
+            let timer = match attributes.get("timer") {
+                Some(timer) => Some(timer.parse()?),
+                None => None,
+            };
+            Kind::Switch(Switch {
+                doors: vec!(),
+                once: once,
+                timer: timer,
+            })
+        } else if let Some(side) = attributes.get("one_way") {
+            Kind::OneWay(direction("one_way", side)?)
+        } else if let Some(impulse) = attributes.get("spring") {
+            let direction = match attributes.get("direction") {
+                Some(value) => direction("direction", value)?,
+                None => [0., 1.],
+            };
+            Kind::Spring(direction, impulse.parse()?)
+        } else if let Some(factor) = attributes.get("boost") {
+            Kind::Boost(factor.parse()?)
+        } else if let Some(threshold) = attributes.get("breakable") {
+            Kind::Breakable(threshold.parse()?)
+        } else if let Some(speed) = attributes.get("conveyor") {
+            Kind::Conveyor(speed.parse()?)
+        } else if let Some(strength) = attributes.get("attractor") {
+            Kind::Attractor(strength.parse()?, float(attributes, "range")?)
+        } else if let Some(water) = attributes.get("water") {
+            match *water {
+                "true" => Kind::Water,
+                "false" => Kind::Wall,
+                _ => return Err(Error::Attribute("water".into(), water.to_string())),
+            }
+        } else if let Some(pickup) = attributes.get("pickup") {
+            Kind::Pickup(match *pickup {
+                "size" => Pickup::Size(float(attributes, "scale")?, float(attributes, "duration")?),
+                "life" => Pickup::Life,
+                _ => return Err(Error::Attribute("pickup".into(), pickup.to_string())),
+            })
+        } else if let Some(goal) = attributes.get("goal") {
+            match *goal {
+                "true" => Kind::Goal,
+                "false" => Kind::Wall,
+                _ => return Err(Error::Attribute("goal".into(), goal.to_string())),
+            }
+        } else if let Some(value) = attributes.get("gravity") {
+            let direction = direction("gravity", value)?;
+            Kind::Gravity(Gravity::To(direction[1].atan2(direction[0])))
+        } else if let Some(value) = attributes.get("gravity_rotate") {
+            Kind::Gravity(Gravity::By(value.parse::<f64>()?.to_radians()))
+        } else {
+            Kind::Wall
+        };
+        self.bodies.push(body);
+        self.kinds.push(kind);
+        self.hookable.push(match attributes.get("hookable") {
+            Some(value) => Some(boolean("hookable", value)?),
+            None => None,
+        });
+        Ok(())
+    }
+
+    /// `hazard="behavior"` with behavior:
+    /// * `patrol`: loop from its position through `points="x,y x,y ..."`
+    /// * `orbit`: turn around `center="x,y"`
+    /// * `chase`: chase the ball when it is closer than `range`
+    ///
+    /// `speed` is in unit per second, points and distances are transformed like the body
+    fn push_hazard(&mut self, body: Body, matrix: [f64; 6], behavior: &str, attributes: &HashMap<String, &str>) -> Result<(), Error> {
+        let behavior = match behavior {
+            "patrol" => {
+                let mut points = vec!(body.pos);
+                for value in attributes.get("points").unwrap_or(&"").split_whitespace() {
+                    points.push(transformed_point(point("points", value)?, matrix));
+                }
+                Behavior::Patrol {
+                    points: points,
+                    next: 0,
+                }
+            },
+            "orbit" => Behavior::Orbit {
+                center: transformed_point(point("center", attributes.get("center").unwrap_or(&""))?, matrix),
+            },
+            "chase" => Behavior::Chase {
+                home: body.pos,
+                range: float(attributes, "range")?*transformed_scale(matrix),
+            },
+            _ => return Err(Error::Attribute("hazard".into(), behavior.into())),
+        };
+        self.hazards.push(Hazard {
+            body: body,
+            speed: float(attributes, "speed")?,
+            behavior: behavior,
+        });
+        Ok(())
+    }
+
+    fn build(mut self, start: [f64; 2], metadata: &HashMap<String, &str>) -> Result<Map, Error> {
+        for &(ref label, switch_id) in &self.switches {
+            let doors = self.doors.iter()
+                .filter(|&&(ref door_label, _)| door_label == label)
+                .map(|&(_, id)| id)
+                .collect::<Vec<_>>();
+            if doors.is_empty() {
+                println!("WARGNING: svg map no door for switch `{}`", label);
+            }
+            if let Kind::Switch(ref mut switch) = self.kinds[switch_id] {
+                switch.doors = doors;
+            }
+        }
+        let hookable = match metadata.get("hookable") {
+            Some(value) => boolean("hookable", value)?,
+            None => true,
+        };
+        Ok(Map {
+            bodies: self.bodies,
+            kinds: self.kinds,
+            hazards: self.hazards,
+            hookable: self.hookable.iter().map(|h| h.unwrap_or(hookable)).collect(),
+            start: start,
+            name: metadata.get("name").unwrap_or(&MAP_FILE).to_string(),
+            time_medals: match metadata.get("time") {
+                Some(value) => Some(thresholds("time", value)?),
+                None => None,
+            },
+            jumps_medals: match metadata.get("jumps") {
+                Some(value) => Some(thresholds("jumps", value)?),
+                None => None,
+            },
+        })
+    }
+}
+
 fn load_map() -> Result<Map, Error> {
     let text = read_map_file()?;
 
     let parser = svg::Tokenizer::from_str(&text);
 
-    let mut bodies = Vec::new();
+    let mut builder = Builder::new();
 
     let mut start = None;
 
+    // attributes of the airjump namespace of the current circle, rect or polygon
+    let mut attributes = HashMap::new();
+
+    // attributes of the airjump namespace of the svg root
+    let mut metadata = HashMap::new();
+    let mut root = false;
+
     // bool is whereas it is start and f64 are cx, cy, r
     let mut circle: Option<(bool, Option<f64>,Option<f64>,Option<f64>)> = None;
 
     // f64 are x, y, width, height
     let mut rect: Option<(Option<f64>,Option<f64>,Option<f64>,Option<f64>)> = None;
 
+    // points of the current polygon
+    let mut polygon: Option<Vec<[f64; 2]>> = None;
+
+    // transform of the current circle, rect or polygon
+    let mut matrix = None;
+
+    // combined transforms of the enclosing groups, the innermost on top
+    let mut groups: Vec<[f64; 6]> = vec!();
+    // whereas attributes are those of a group
+    let mut group = false;
+
     for next in parser {
         match next? {
-            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::Circle), .. }) => circle = Some((false, None, None, None)),
-            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::Rect), .. }) => rect = Some((None, None, None, None)),
+            // names of the namespace may also be svg attributes like `mode` or `points`
+            svg::Token::Attribute(svg::QName { prefix: "airjump", local }, value) => {
+                let name = match local {
+                    svg::Name::Xml(name) => name.to_string(),
+                    svg::Name::Svg(id) => id.name().to_string(),
+                };
+                if circle.is_some() || rect.is_some() || polygon.is_some() {
+                    attributes.insert(name, value.to_str());
+                } else if root {
+                    metadata.insert(name, value.to_str());
+                }
+            },
+            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::Circle), .. }) => {
+                group = false;
+                circle = Some((false, None, None, None));
+                matrix = None;
+                attributes.clear();
+            },
+            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::Rect), .. }) => {
+                group = false;
+                rect = Some((None, None, None, None));
+                matrix = None;
+                attributes.clear();
+            },
+            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::Polygon), .. }) => {
+                group = false;
+                polygon = Some(vec!());
+                matrix = None;
+                attributes.clear();
+            },
+            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::Svg), .. }) => root = true,
+            svg::Token::ElementStart(svg::QName { local: svg::Name::Svg(ElementId::G), .. }) => {
+                root = false;
+                group = true;
+                let outer = groups.last().cloned().unwrap_or([1., 0., 0., 1., 0., 0.]);
+                groups.push(outer);
+            },
+            svg::Token::ElementStart(_) => {
+                root = false;
+                group = false;
+            },
+            svg::Token::ElementEnd(ElementEnd::Open) => group = false,
+            svg::Token::ElementEnd(ElementEnd::Close(svg::QName { local: svg::Name::Svg(ElementId::G), .. })) => {
+                groups.pop();
+            },
+            svg::Token::ElementEnd(ElementEnd::Empty) if group => {
+                group = false;
+                groups.pop();
+            },
             svg::Token::ElementEnd(ElementEnd::Empty) => {
+                let matrix = matrix.take().unwrap_or([1., 0., 0., 1., 0., 0.]);
+                let matrix = groups.last().map_or(matrix, |&outer| compose(outer, matrix));
                 if let Some(circle) = circle.take() {
                     match circle {
                         (true, Some(x), Some(y), _) => {
                             if start.is_some() {
                                 println!("WARGNING: svg map redefinition of start");
                             }
-                            start = Some([x, y]);
+                            start = Some(transformed_point([x, y], matrix));
                         }
-                        (false, Some(x), Some(y), Some(r)) => bodies.push(Body {
+                        (false, Some(x), Some(y), Some(r)) => builder.push(transformed(Body {
                             pos: [x, y],
                             shape: Shape::Circle(r),
-                        }),
+                        }, matrix), matrix, &attributes)?,
                         _ => println!("WARGNING: svg map incomplete circle definition"),
                     }
                 } else if let Some(rect) = rect.take() {
                     match rect {
-                        (Some(x), Some(y), Some(w), Some(h)) => bodies.push(Body {
+                        (Some(x), Some(y), Some(w), Some(h)) => builder.push(transformed(Body {
                             pos: [x+w/2., y-h/2.],
                             shape: Shape::Rectangle(w, h),
-                        }),
+                        }, matrix), matrix, &attributes)?,
                         _ => println!("WARGNING: svg map incomplete rect definition"),
                     }
+                } else if let Some(points) = polygon.take() {
+                    if points.len() < 3 {
+                        println!("WARGNING: svg map incomplete polygon definition");
+                    } else {
+                        let points = match transformed(Body { pos: [0., 0.], shape: Shape::Polygon(points) }, matrix) {
+                            Body { pos, shape: Shape::Polygon(vertices) } => vertices.iter().map(|&v| [pos[0] + v[0], pos[1] + v[1]]).collect(),
+                            _ => unreachable!(),
+                        };
+                        // the pieces stay one body so the element keeps a single kind
+                        builder.push(Body::compound(convex_pieces(points)), matrix, &attributes)?;
+                    }
                 }
             },
             svg::Token::Attribute(svg::QName { local: svg::Name::Svg(AttributeId::Id), .. }, value) => {
@@ -124,6 +743,19 @@ fn load_map() -> Result<Map, Error> {
                     circle.3 = Some(value.to_str().parse()?);
                 }
             },
+            svg::Token::Attribute(svg::QName { local: svg::Name::Svg(AttributeId::Points), .. }, value) => {
+                if let Some(ref mut polygon) = polygon {
+                    *polygon = points(value.to_str())?;
+                }
+            },
+            svg::Token::Attribute(svg::QName { local: svg::Name::Svg(AttributeId::Transform), .. }, value) => {
+                if group {
+                    let outer = groups.last_mut().unwrap();
+                    *outer = compose(*outer, transform(value.to_str())?);
+                } else if circle.is_some() || rect.is_some() || polygon.is_some() {
+                    matrix = Some(transform(value.to_str())?);
+                }
+            },
             svg::Token::Attribute(svg::QName { local: svg::Name::Svg(AttributeId::X), .. }, value) => {
                 if let Some(ref mut rect) = rect {
                     rect.0 = Some(value.to_str().parse()?);
@@ -148,15 +780,105 @@ fn load_map() -> Result<Map, Error> {
         }
     }
 
-    Ok(Map {
-        bodies: bodies,
-        start: start.unwrap_or_else(|| {
-            println!("WARGNING: svg map no start definition");
-            [0., 0.]
-        }),
-    })
+    builder.build(start.unwrap_or_else(|| {
+        println!("WARGNING: svg map no start definition");
+        [0., 0.]
+    }), &metadata)
 }
 
 lazy_static! {
     pub static ref MAP: Map = load_map().ok_or_exit();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(value: &str) -> [f64; 6] {
+        transform(value).ok().expect("invalid transform")
+    }
+
+    fn assert_near(a: [f64; 2], b: [f64; 2]) {
+        assert!((a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9, "{:?} instead of {:?}", a, b);
+    }
+
+    /// svg coordinates of a point mapped by the matrix
+    fn apply(matrix: [f64; 6], point: [f64; 2]) -> [f64; 2] {
+        let point = transformed_point([point[0], -point[1]], matrix);
+        [point[0], -point[1]]
+    }
+
+    #[test]
+    fn rotate_around_center() {
+        let rotate = matrix("rotate(90 10 20)");
+        assert_near(apply(rotate, [10., 20.]), [10., 20.]);
+        assert_near(apply(rotate, [11., 20.]), [10., 21.]);
+        assert_near(apply(rotate, [10., 21.]), [9., 20.]);
+        // same as translate rotate translate back
+        let steps = matrix("translate(10, 20) rotate(90) translate(-10 -20)");
+        assert_near(apply(steps, [3., 4.]), apply(rotate, [3., 4.]));
+    }
+
+    #[test]
+    fn nested_groups() {
+        // <g transform="translate(100,0)"><g transform="scale(2)"><rect transform="translate(0,5)"/>
+        let combined = compose(compose(matrix("translate(100,0)"), matrix("scale(2)")), matrix("translate(0,5)"));
+        assert_near(apply(combined, [1., 1.]), [102., 12.]);
+        // a list applies its last transform first
+        assert_near(apply(matrix("translate(100,0) scale(2) translate(0,5)"), [1., 1.]), [102., 12.]);
+        assert_near(apply(matrix("skewX(45)"), [0., 2.]), [2., 2.]);
+        assert!(transform("spin(3)").is_err());
+    }
+
+    #[test]
+    fn transformed_shapes() {
+        // svg (1, 1) turns to (-1, 1)
+        let body = transformed(Body { pos: [1., -1.], shape: Shape::Rectangle(4., 2.) }, matrix("rotate(90)"));
+        assert_near(body.pos, [-1., -1.]);
+        match body.shape {
+            Shape::Rectangle(w, h) => assert_near([w, h], [2., 4.]),
+            _ => panic!("not axis aligned"),
+        }
+        let body = transformed(Body { pos: [0., 0.], shape: Shape::Circle(1.) }, matrix("scale(2 8)"));
+        match body.shape {
+            Shape::Circle(r) => assert!((r - 4.).abs() < 1e-9),
+            _ => unreachable!(),
+        }
+    }
+
+    fn area(points: &[[f64; 2]]) -> f64 {
+        let n = points.len();
+        (0..n).map(|i| cross([0., 0.], points[i], points[(i + 1) % n])).sum::<f64>()/2.
+    }
+
+    /// the pieces are convex, counterclockwise and cover the polygon
+    fn assert_pieces(points: Vec<[f64; 2]>, count: usize) {
+        let total = area(&points).abs();
+        let pieces = convex_pieces(points);
+        assert_eq!(pieces.len(), count);
+        for piece in &pieces {
+            let all: Vec<_> = (0..piece.len()).collect();
+            assert!(is_convex(piece, &all));
+            assert!(area(piece) > 0.);
+        }
+        assert!((pieces.iter().map(|piece| area(piece)).sum::<f64>() - total).abs() < 1e-9);
+    }
+
+    #[test]
+    fn convex_pieces_of_l_and_u() {
+        let l = vec!([0., 0.], [4., 0.], [4., 1.], [1., 1.], [1., 4.], [0., 4.]);
+        assert_pieces(l.clone(), 2);
+        // clockwise
+        assert_pieces(l.into_iter().rev().collect(), 2);
+        let u = vec!([0., 0.], [3., 0.], [3., 3.], [2., 3.], [2., 1.], [1., 1.], [1., 3.], [0., 3.]);
+        assert_pieces(u, 3);
+        let square = vec!([0., 0.], [1., 0.], [1., 1.], [0., 1.]);
+        assert_pieces(square, 1);
+    }
+
+    #[test]
+    fn points_drop_repeats() {
+        let points = points("0,0 0,0 4,0 0,4 0,0").ok().unwrap();
+        assert_eq!(points, vec!([0., 0.], [4., 0.], [0., -4.]));
+    }
+}
@@ -23,6 +23,10 @@ pub fn sub(p1: [f64; 2], p2: [f64; 2]) -> [f64; 2] {
     [p1[0]-p2[0], p1[1]-p2[1]]
 }
 #[inline]
+pub fn dot(p1: [f64; 2], p2: [f64; 2]) -> f64 {
+    p1[0]*p2[0] + p1[1]*p2[1]
+}
+#[inline]
 pub fn into_polar(p: [f64; 2]) -> [f64; 2] {
     [norm(p), angle(p)]
 }
@@ -30,3 +34,8 @@ pub fn into_polar(p: [f64; 2]) -> [f64; 2] {
 pub fn from_polar(p: [f64; 2]) -> [f64; 2] {
     [p[0]*p[1].cos(), p[0]*p[1].sin()]
 }
+#[inline]
+pub fn rotate(p: [f64; 2], angle: f64) -> [f64; 2] {
+    let (s, c) = angle.sin_cos();
+    [c*p[0] - s*p[1], s*p[0] + c*p[1]]
+}
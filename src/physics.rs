@@ -1,3 +1,5 @@
+use math::{normalize, norm, angle, dot, add, sub, mul, rotate};
+
 #[derive(Clone)]
 pub struct Body {
     pub pos: [f64; 2],
@@ -5,6 +7,47 @@ pub struct Body {
 }
 
 impl Body {
+    /// A convex polygon from its vertices counterclockwise, positioned at the center of their bounds
+    pub fn polygon(vertices: Vec<[f64; 2]>) -> Body {
+        let (min, max) = vertices.iter().fold(([::std::f64::INFINITY; 2], [::std::f64::NEG_INFINITY; 2]), |(min, max), v| {
+            ([min[0].min(v[0]), min[1].min(v[1])], [max[0].max(v[0]), max[1].max(v[1])])
+        });
+        let pos = mul(0.5, add(min, max));
+        Body {
+            pos: pos,
+            shape: Shape::Polygon(vertices.iter().map(|&v| sub(v, pos)).collect()),
+        }
+    }
+    /// A polygon split in convex pieces, positioned at the center of their bounds
+    pub fn compound(mut pieces: Vec<Vec<[f64; 2]>>) -> Body {
+        if pieces.len() == 1 {
+            return Body::polygon(pieces.remove(0))
+        }
+        let parts: Vec<_> = pieces.into_iter().map(Body::polygon).collect();
+        let (min, max) = parts.iter().fold(([::std::f64::INFINITY; 2], [::std::f64::NEG_INFINITY; 2]), |(min, max), part| {
+            let (w, h) = part.shape.size();
+            ([min[0].min(part.pos[0] - w/2.), min[1].min(part.pos[1] - h/2.)],
+             [max[0].max(part.pos[0] + w/2.), max[1].max(part.pos[1] + h/2.)])
+        });
+        let pos = mul(0.5, add(min, max));
+        Body {
+            pos: pos,
+            shape: Shape::Compound(parts.into_iter().map(|part| Body {
+                pos: sub(part.pos, pos),
+                shape: part.shape,
+            }).collect()),
+        }
+    }
+    /// the parts of a compound at their position in the world, else the body itself
+    pub fn parts(&self) -> Vec<Body> {
+        match self.shape {
+            Shape::Compound(ref parts) => parts.iter().map(|part| Body {
+                pos: add(self.pos, part.pos),
+                shape: part.shape.clone(),
+            }).collect(),
+            _ => vec!(self.clone()),
+        }
+    }
     /// if A collide with B then collision must represent
     /// the smallest vector to move A so it doesn't collide anymore
     pub fn collide(&self, other: &Body) -> Option<Collision> {
@@ -14,6 +57,148 @@ impl Body {
     pub fn cells(&self, unit: f64) -> Vec<[i32; 2]> {
         self.shape.cells(unit, self.pos)
     }
+    /// distance a circle of radius moves from origin along the unit direction before
+    /// touching the body if it is below length, zero if they already overlap
+    pub fn sweep(&self, radius: f64, origin: [f64; 2], direction: [f64; 2], length: f64) -> Option<f64> {
+        // the circle center hits the body grown by radius
+        let parts = match self.shape {
+            Shape::Circle(r) => vec!(Body {
+                pos: self.pos,
+                shape: Shape::Circle(r + radius),
+            }),
+            Shape::Rectangle(width, height) => {
+                let mut parts = vec!(
+                    Body {
+                        pos: self.pos,
+                        shape: Shape::Rectangle(width + 2.*radius, height),
+                    },
+                    Body {
+                        pos: self.pos,
+                        shape: Shape::Rectangle(width, height + 2.*radius),
+                    },
+                );
+                for &(x, y) in &[(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+                    parts.push(Body {
+                        pos: [self.pos[0] + x*width/2., self.pos[1] + y*height/2.],
+                        shape: Shape::Circle(radius),
+                    });
+                }
+                parts
+            },
+            Shape::OrientedRectangle(width, height, angle) => {
+                let local = Body {
+                    pos: [0., 0.],
+                    shape: Shape::Rectangle(width, height),
+                };
+                return local.sweep(radius, rotate(sub(origin, self.pos), -angle), rotate(direction, -angle), length)
+            },
+            Shape::Polygon(ref vertices) => {
+                // the polygon, a circle on each vertex and a rectangle along each edge
+                let mut parts = vec!(self.clone());
+                for (i, &v) in vertices.iter().enumerate() {
+                    let edge = sub(vertices[(i + 1) % vertices.len()], v);
+                    let v = add(self.pos, v);
+                    parts.push(Body {
+                        pos: v,
+                        shape: Shape::Circle(radius),
+                    });
+                    parts.push(Body {
+                        pos: add(v, mul(0.5, edge)),
+                        shape: Shape::OrientedRectangle(norm(edge), 2.*radius, angle(edge)),
+                    });
+                }
+                parts
+            },
+            Shape::Compound(_) => {
+                return self.parts().iter()
+                    .filter_map(|part| part.sweep(radius, origin, direction, length))
+                    .fold(None, |min: Option<f64>, distance| Some(min.map_or(distance, |min| min.min(distance))))
+            },
+        };
+        parts.iter()
+            .filter_map(|part| part.raycast(origin, direction, length))
+            .fold(None, |min: Option<f64>, distance| Some(min.map_or(distance, |min| min.min(distance))))
+    }
+    /// distance from origin along the unit direction to the body if it is below length,
+    /// zero if origin is inside
+    pub fn raycast(&self, origin: [f64; 2], direction: [f64; 2], length: f64) -> Option<f64> {
+        let distance = match self.shape {
+            Shape::Circle(radius) => {
+                let delta = sub(origin, self.pos);
+                let b = dot(delta, direction);
+                let c = dot(delta, delta) - radius.powi(2);
+                let discriminant = b.powi(2) - c;
+                if (c > 0. && b > 0.) || discriminant < 0. {
+                    return None
+                }
+                (-b - discriminant.sqrt()).max(0.)
+            },
+            Shape::Rectangle(width, height) => {
+                let mut min = 0.;
+                let mut max = length;
+                for &(axis, half) in &[(0, width/2.), (1, height/2.)] {
+                    let (low, high) = (self.pos[axis] - half, self.pos[axis] + half);
+                    if direction[axis] == 0. {
+                        if origin[axis] < low || origin[axis] > high {
+                            return None
+                        }
+                    } else {
+                        let t0 = (low - origin[axis])/direction[axis];
+                        let t1 = (high - origin[axis])/direction[axis];
+                        min = t0.min(t1).max(min);
+                        max = t0.max(t1).min(max);
+                        if min > max {
+                            return None
+                        }
+                    }
+                }
+                min
+            },
+            Shape::OrientedRectangle(width, height, angle) => {
+                let local = Body {
+                    pos: [0., 0.],
+                    shape: Shape::Rectangle(width, height),
+                };
+                return local.raycast(rotate(sub(origin, self.pos), -angle), rotate(direction, -angle), length)
+            },
+            Shape::Polygon(ref vertices) => {
+                // clip the ray by the half plane inside each edge
+                let mut min = 0.;
+                let mut max = length;
+                for (i, &v) in vertices.iter().enumerate() {
+                    let next = vertices[(i + 1) % vertices.len()];
+                    if v == next {
+                        continue
+                    }
+                    let normal = outward_normal(v, next);
+                    let distance = dot(normal, sub(origin, add(self.pos, v)));
+                    let speed = dot(normal, direction);
+                    if speed == 0. {
+                        if distance > 0. {
+                            return None
+                        }
+                    } else {
+                        let t = -distance/speed;
+                        if speed < 0. {
+                            min = t.max(min);
+                        } else {
+                            max = t.min(max);
+                        }
+                        if min > max {
+                            return None
+                        }
+                    }
+                }
+                min
+            },
+            Shape::Compound(_) => {
+                return self.parts().iter()
+                    .filter_map(|part| part.raycast(origin, direction, length))
+                    .fold(None, |min: Option<f64>, distance| Some(min.map_or(distance, |min| min.min(distance))))
+            },
+        };
+        if distance <= length { Some(distance) } else { None }
+    }
 }
 
 /// if A collide with B then collision must represent
@@ -30,6 +215,15 @@ impl Collision {
             dy: -self.dy,
         }
     }
+    /// the unit vector along which A is moved out of B
+    pub fn normal(&self) -> [f64; 2] {
+        normalize([self.dx, self.dy])
+    }
+    /// the normal rotated clockwise, the surface of B goes clockwise along it
+    pub fn tangent(&self) -> [f64; 2] {
+        let normal = self.normal();
+        [normal[1], -normal[0]]
+    }
     pub fn push(&mut self, res: Collision) {
         if res.dx.abs() > self.dx.abs() { self.dx = res.dx; }
         if res.dy.abs() > self.dy.abs() { self.dy = res.dy; }
@@ -42,14 +236,51 @@ pub enum Shape {
     Circle(f64),
     /// width and height
     Rectangle(f64, f64),
+    /// width, height and angle in radians counterclockwise
+    OrientedRectangle(f64, f64, f64),
+    /// convex, vertices counterclockwise relative to the position
+    Polygon(Vec<[f64; 2]>),
+    /// convex polygons relative to the position that act as one body
+    Compound(Vec<Body>),
 }
 impl Shape {
+    /// width and height of the axis aligned rectangle around the shape
+    pub fn size(&self) -> (f64, f64) {
+        match *self {
+            Shape::Circle(r) => (r * 2., r * 2.),
+            Shape::Rectangle(w, h) => (w, h),
+            Shape::OrientedRectangle(w, h, angle) => {
+                let (s, c) = (angle.sin().abs(), angle.cos().abs());
+                (w*c + h*s, w*s + h*c)
+            },
+            Shape::Polygon(ref vertices) => {
+                let (w2, h2) = vertices.iter().fold((0f64, 0f64), |(w2, h2), v| (v[0].abs().max(w2), v[1].abs().max(h2)));
+                (w2 * 2., h2 * 2.)
+            },
+            Shape::Compound(ref parts) => {
+                let (w2, h2) = parts.iter().fold((0f64, 0f64), |(w2, h2), part| {
+                    let (w, h) = part.shape.size();
+                    ((part.pos[0].abs() + w/2.).max(w2), (part.pos[1].abs() + h/2.).max(h2))
+                });
+                (w2 * 2., h2 * 2.)
+            },
+        }
+    }
+    /// vertices counterclockwise relative to the position, none for circles and compounds
+    fn vertices(&self) -> Vec<[f64; 2]> {
+        match *self {
+            Shape::Circle(_) | Shape::Compound(_) => vec!(),
+            Shape::Rectangle(w, h) => rectangle_vertices(w, h, 0.),
+            Shape::OrientedRectangle(w, h, angle) => rectangle_vertices(w, h, angle),
+            Shape::Polygon(ref vertices) => vertices.clone(),
+        }
+    }
     fn cells(&self, unit: f64, pos: [f64; 2]) -> Vec<[i32; 2]> {
         use ::std::f64::EPSILON;
 
-        let (w2, h2) = match *self {
-            Shape::Circle(r) => (r, r),
-            Shape::Rectangle(w, h) => (w / 2., h / 2.),
+        let (w2, h2) = {
+            let (w, h) = self.size();
+            (w / 2., h / 2.)
         };
 
         let min_x = ((pos[0] - w2 + EPSILON)/unit).floor() as i32;
@@ -74,7 +305,124 @@ fn shape_collision(a_pos: [f64;2], a_shape: &Shape, b_pos: [f64;2], b_shape: &Sh
         (&Circle(a_radius), &Rectangle(b_w, b_h)) => circle_rectangle_collision(a_pos, a_radius, b_pos, b_w, b_h),
         (&Rectangle(a_w, a_h), &Rectangle(b_w, b_h)) => rectangle_rectangle_collision(a_pos, a_w, a_h, b_pos, b_w, b_h),
         (&Rectangle(a_w, a_h), &Circle(b_radius)) => circle_rectangle_collision(b_pos, b_radius, a_pos, a_w, a_h).map(|col| col.opposite()),
+        (&Circle(a_radius), &OrientedRectangle(b_w, b_h, b_angle)) => circle_oriented_rectangle_collision(a_pos, a_radius, b_pos, b_w, b_h, b_angle),
+        (&OrientedRectangle(a_w, a_h, a_angle), &Circle(b_radius)) => circle_oriented_rectangle_collision(b_pos, b_radius, a_pos, a_w, a_h, a_angle).map(|col| col.opposite()),
+        (&Circle(a_radius), &Polygon(ref b_vertices)) => circle_polygon_collision(a_pos, a_radius, b_pos, b_vertices),
+        (&Polygon(ref a_vertices), &Circle(b_radius)) => circle_polygon_collision(b_pos, b_radius, a_pos, a_vertices).map(|col| col.opposite()),
+        (&Compound(ref a_parts), _) => compound_collision(a_pos, a_parts, b_pos, b_shape),
+        (_, &Compound(ref b_parts)) => compound_collision(b_pos, b_parts, a_pos, a_shape).map(|col| col.opposite()),
+        // oriented rectangles are polygons too
+        _ => polygon_polygon_collision(a_pos, &a_shape.vertices(), b_pos, &b_shape.vertices()),
+    }
+}
+
+fn rectangle_vertices(width: f64, height: f64, angle: f64) -> Vec<[f64; 2]> {
+    [[-1., -1.], [1., -1.], [1., 1.], [-1., 1.]].iter()
+        .map(|c| rotate([c[0]*width/2., c[1]*height/2.], angle))
+        .collect()
+}
+
+/// unit normal of the edge from a to b pointing out of a counterclockwise polygon
+fn outward_normal(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    let edge = sub(b, a);
+    normalize([edge[1], -edge[0]])
+}
+
+/// outward normals of the edges of a counterclockwise polygon, repeated vertices have none
+fn edge_normals(vertices: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    (0..vertices.len())
+        .map(|i| (vertices[i], vertices[(i + 1) % vertices.len()]))
+        .filter(|&(a, b)| a != b)
+        .map(|(a, b)| outward_normal(a, b))
+        .collect()
+}
+
+fn project(pos: [f64; 2], vertices: &[[f64; 2]], axis: [f64; 2]) -> (f64, f64) {
+    let center = dot(pos, axis);
+    vertices.iter().fold((::std::f64::INFINITY, ::std::f64::NEG_INFINITY), |(min, max), &v| {
+        let p = center + dot(v, axis);
+        (min.min(p), max.max(p))
+    })
+}
+
+/// separating axis theorem: A and B collide if their projections overlap on all the axes,
+/// A is moved along the axis of the smallest overlap.
+/// project gives the interval covered by a body on an axis
+fn separating_axes<A, B>(axes: &[[f64; 2]], a_project: A, b_project: B) -> Option<Collision>
+    where A: Fn([f64; 2]) -> (f64, f64), B: Fn([f64; 2]) -> (f64, f64)
+{
+    let mut min: Option<[f64; 2]> = None;
+    for &axis in axes {
+        let (a_min, a_max) = a_project(axis);
+        let (b_min, b_max) = b_project(axis);
+        if a_min >= b_max || b_min >= a_max {
+            return None
+        }
+        // move A forward or backward along the axis
+        let forward = b_max - a_min;
+        let backward = b_min - a_max;
+        let delta = if forward < -backward { forward } else { backward };
+        if min.map_or(true, |min| delta.abs() < norm(min)) {
+            min = Some(mul(delta, axis));
+        }
+    }
+    min.map(|delta| Collision {
+        dx: delta[0],
+        dy: delta[1],
+    })
+}
+
+fn circle_polygon_collision(a_pos: [f64;2], a_radius: f64, b_pos: [f64;2], b_vertices: &[[f64; 2]]) -> Option<Collision> {
+    let mut axes = edge_normals(b_vertices);
+    // the axis from the closest vertex separates the circle from a corner
+    let closest = b_vertices.iter()
+        .map(|&v| sub(a_pos, add(b_pos, v)))
+        .fold(None, |closest: Option<[f64; 2]>, delta| match closest {
+            Some(closest) if norm(closest) <= norm(delta) => Some(closest),
+            _ => Some(delta),
+        });
+    if let Some(closest) = closest {
+        if norm(closest) > 0. {
+            axes.push(normalize(closest));
+        }
     }
+    separating_axes(&axes,
+        |axis| {
+            let center = dot(a_pos, axis);
+            (center - a_radius, center + a_radius)
+        },
+        |axis| project(b_pos, b_vertices, axis))
+}
+
+fn polygon_polygon_collision(a_pos: [f64;2], a_vertices: &[[f64; 2]], b_pos: [f64;2], b_vertices: &[[f64; 2]]) -> Option<Collision> {
+    let mut axes = edge_normals(a_vertices);
+    axes.extend(edge_normals(b_vertices));
+    separating_axes(&axes, |axis| project(a_pos, a_vertices, axis), |axis| project(b_pos, b_vertices, axis))
+}
+
+/// the collisions of the parts with B pushed together like the walls touching a ball
+fn compound_collision(a_pos: [f64;2], a_parts: &[Body], b_pos: [f64;2], b_shape: &Shape) -> Option<Collision> {
+    a_parts.iter()
+        .filter_map(|part| shape_collision(add(a_pos, part.pos), &part.shape, b_pos, b_shape))
+        .fold(None, |collision: Option<Collision>, c| match collision {
+            Some(mut collision) => {
+                collision.push(c);
+                Some(collision)
+            },
+            None => Some(c),
+        })
+}
+
+/// the collision with the axis aligned rectangle computed in the frame of the oriented one
+fn circle_oriented_rectangle_collision(a_pos: [f64;2], a_radius: f64, b_pos: [f64;2], b_width: f64, b_height: f64, b_angle: f64) -> Option<Collision> {
+    let local = rotate(sub(a_pos, b_pos), -b_angle);
+    circle_rectangle_collision(local, a_radius, [0., 0.], b_width, b_height).map(|col| {
+        let delta = rotate([col.dx, col.dy], b_angle);
+        Collision {
+            dx: delta[0],
+            dy: delta[1],
+        }
+    })
 }
 
 fn circle_circle_collision(a_pos: [f64;2], a_rad: f64, b_pos: [f64;2], b_rad: f64) -> Option<Collision> {
@@ -233,3 +581,108 @@ fn line_equation_from_points(p: [f64; 2], q: [f64; 2]) -> (f64, f64, f64) {
     (a, b, c)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+    use std::f64::consts::FRAC_PI_4;
+
+    fn assert_near(value: Option<f64>, expected: f64) {
+        let value = value.expect("no hit");
+        assert!((value - expected).abs() < 1e-9, "{} instead of {}", value, expected);
+    }
+
+    /// a thin vertical wall at x = 5 from y = -2 to 2
+    fn thin_wall() -> Body {
+        Body {
+            pos: [5., 0.],
+            shape: Shape::Rectangle(0.2, 4.),
+        }
+    }
+
+    #[test]
+    fn sweep_stops_at_thin_wall() {
+        assert_near(thin_wall().sweep(0.5, [0., 0.], [1., 0.], 10.), 4.4);
+        // the wall is past the end of the move
+        assert_eq!(thin_wall().sweep(0.5, [0., 0.], [1., 0.], 4.), None);
+        // the ball already overlaps the wall
+        assert_near(thin_wall().sweep(0.5, [4.7, 0.], [1., 0.], 10.), 0.);
+    }
+
+    #[test]
+    fn sweep_hits_corners() {
+        // 0.3 above the top of the wall, the corner is 0.4 in front of the center on contact
+        assert_near(thin_wall().sweep(0.5, [0., 2.3], [1., 0.], 10.), 4.5);
+        assert_near(thin_wall().sweep(0.5, [0., -2.3], [1., 0.], 10.), 4.5);
+        assert_eq!(thin_wall().sweep(0.5, [0., 2.6], [1., 0.], 10.), None);
+    }
+
+    #[test]
+    fn sweep_hits_oriented_rectangles() {
+        let upright = Body {
+            pos: [5., 0.],
+            shape: Shape::OrientedRectangle(4., 0.2, FRAC_PI_2),
+        };
+        assert_near(upright.sweep(0.5, [0., 0.], [1., 0.], 10.), 4.4);
+        assert_near(upright.sweep(0.5, [0., 2.3], [1., 0.], 10.), 4.5);
+
+        // the near face is 0.1 + 0.5 from the center along its diagonal normal
+        let tilted = Body {
+            pos: [5., 0.],
+            shape: Shape::OrientedRectangle(0.2, 4., FRAC_PI_4),
+        };
+        assert_near(tilted.sweep(0.5, [0., 0.], [1., 0.], 10.), 5. - 0.6*2f64.sqrt());
+    }
+
+    #[test]
+    fn polygon_agrees_with_rectangle() {
+        let square = Shape::Polygon(rectangle_vertices(2., 2., 0.));
+        // overlapping a face, a corner, inside and apart
+        for &pos in &[[1.3, 0.2], [-0.4, -1.2], [1.2, 1.2], [-1.25, 1.3], [0.3, 0.1], [2., 0.], [1.4, 1.4]] {
+            let polygon = shape_collision(pos, &Shape::Circle(0.5), [0., 0.], &square);
+            let rectangle = circle_rectangle_collision(pos, 0.5, [0., 0.], 2., 2.);
+            match (polygon, rectangle) {
+                (Some(p), Some(r)) => assert!((p.dx - r.dx).abs() < 1e-9 && (p.dy - r.dy).abs() < 1e-9,
+                    "{:?}: polygon ({}, {}) rectangle ({}, {})", pos, p.dx, p.dy, r.dx, r.dy),
+                (None, None) => (),
+                _ => panic!("{:?}: only one of them collides", pos),
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_vertices() {
+        let ball = Body {
+            pos: [1., 1.],
+            shape: Shape::Circle(1.),
+        };
+        let polygon = Body::polygon(vec!([0., 0.], [0., 0.], [4., 0.], [0., 4.]));
+        let collision = ball.collide(&polygon).expect("no collision");
+        assert!(collision.dx.is_finite() && collision.dy.is_finite());
+    }
+
+    #[test]
+    fn compound_acts_as_one_body() {
+        // an L: a bar along the bottom and a post on the left
+        let l = Body::compound(vec!(
+            vec!([0., 0.], [4., 0.], [4., 1.], [1., 1.]),
+            vec!([0., 0.], [1., 1.], [1., 4.], [0., 4.]),
+        ));
+        assert_eq!(l.pos, [2., 2.]);
+        assert_eq!(l.shape.size(), (4., 4.));
+        let on_bar = Body {
+            pos: [3., 1.4],
+            shape: Shape::Circle(0.5),
+        };
+        let collision = on_bar.collide(&l).expect("no collision");
+        assert!(collision.dx.abs() < 1e-9 && (collision.dy - 0.1).abs() < 1e-9);
+        let in_corner = Body {
+            pos: [3., 3.],
+            shape: Shape::Circle(0.5),
+        };
+        assert!(in_corner.collide(&l).is_none());
+        assert_near(l.sweep(0.5, [3., 6.], [0., -1.], 10.), 4.5);
+        assert_near(l.raycast([6., 3.], [-1., 0.], 10.), 5.);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::{BTreeMap, BTreeSet};
+use toml_file::{self, Error};
+
+const PROGRESS_FILE: &'static str = "progress.toml";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Medal {
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl Medal {
+    /// thresholds are the maximum values for bronze, silver and gold
+    pub fn award<T: PartialOrd>(value: T, thresholds: [T; 3]) -> Option<Medal> {
+        if value <= thresholds[2] {
+            Some(Medal::Gold)
+        } else if value <= thresholds[1] {
+            Some(Medal::Silver)
+        } else if value <= thresholds[0] {
+            Some(Medal::Bronze)
+        } else {
+            None
+        }
+    }
+}
+
+/// A jump of a run, enough to replay it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayJump {
+    /// number of updates since the start of the run
+    pub tick: u64,
+    pub angle: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Level {
+    pub best_time: Option<f64>,
+    pub best_jumps: Option<u32>,
+    pub time_medal: Option<Medal>,
+    pub jumps_medal: Option<Medal>,
+    /// best run in fewest jumps mode, ties are broken by time
+    pub fewest_jumps: Option<u32>,
+    pub fewest_jumps_time: Option<f64>,
+    /// best height in endless mode
+    pub best_height: Option<f64>,
+    #[serde(default)]
+    pub fewest_jumps_replay: Vec<ReplayJump>,
+}
+
+impl Level {
+    /// Keep the best of each record
+    pub fn record(&mut self, time: f64, jumps: u32, time_medal: Option<Medal>, jumps_medal: Option<Medal>) {
+        self.best_time = Some(self.best_time.map_or(time, |best| best.min(time)));
+        self.best_jumps = Some(self.best_jumps.map_or(jumps, |best| best.min(jumps)));
+        self.record_medals(time_medal, jumps_medal);
+    }
+
+    /// Keep the best medals
+    pub fn record_medals(&mut self, time_medal: Option<Medal>, jumps_medal: Option<Medal>) {
+        self.time_medal = self.time_medal.max(time_medal);
+        self.jumps_medal = self.jumps_medal.max(jumps_medal);
+    }
+
+    /// Keep the height if it is the best, return whereas it is
+    pub fn record_height(&mut self, height: f64) -> bool {
+        let best = self.best_height.map_or(true, |best| height > best);
+        if best {
+            self.best_height = Some(height);
+        }
+        best
+    }
+
+    /// Keep the run if it is the best in fewest jumps mode, return whereas it is
+    pub fn record_fewest_jumps(&mut self, jumps: u32, time: f64, replay: Vec<ReplayJump>) -> bool {
+        let best = match (self.fewest_jumps, self.fewest_jumps_time) {
+            (Some(best_jumps), Some(best_time)) => jumps < best_jumps || (jumps == best_jumps && time < best_time),
+            _ => true,
+        };
+        if best {
+            self.fewest_jumps = Some(jumps);
+            self.fewest_jumps_time = Some(time);
+            self.fewest_jumps_replay = replay;
+        }
+        best
+    }
+}
+
+/// What the player achieved, saved on disk
+#[derive(Serialize, Deserialize, Default)]
+pub struct Progress {
+    /// ids of unlocked achievements
+    #[serde(default)]
+    pub achievements: BTreeSet<String>,
+    #[serde(default)]
+    pub levels: BTreeMap<String, Level>,
+}
+
+impl Progress {
+    /// Progress is empty if the file doesn't exist
+    pub fn load() -> Result<Progress, Error> {
+        toml_file::load(PROGRESS_FILE)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        toml_file::save(PROGRESS_FILE, self)
+    }
+
+    pub fn level(&mut self, name: &str) -> &mut Level {
+        self.levels.entry(name.to_string()).or_insert_with(Level::default)
+    }
+}
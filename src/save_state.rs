@@ -0,0 +1,168 @@
+use progress::ReplayJump;
+use std::collections::BTreeMap;
+use toml_file::{self, Error};
+
+const SAVE_STATES_FILE: &'static str = "save_states.toml";
+
+/// Everything needed to resume a run, made of plain values so it can be written on disk
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveState {
+    #[serde(with = "vector")]
+    pub ball_pos: [f64; 2],
+    #[serde(with = "vector")]
+    pub ball_vel: [f64; 2],
+    #[serde(with = "vector")]
+    pub ball_acc: [f64; 2],
+    pub ball_radius: f64,
+    #[serde(default)]
+    pub ball_angle: f64,
+    #[serde(default)]
+    pub ball_spin: f64,
+    pub air_jump: bool,
+    pub size_timer: Option<f64>,
+    pub in_water: bool,
+    pub gravity_angle: f64,
+    pub camera_angle: f64,
+    pub run_time: f64,
+    pub jumps: u32,
+    pub ticks: u64,
+    /// none in casual play or in save states written before lives
+    #[serde(default)]
+    pub lives: Option<u32>,
+    /// ids of disabled walls: open doors and shattered walls
+    pub disabled: Vec<usize>,
+    pub gravity_triggers: Vec<usize>,
+    pub replay: Vec<ReplayJump>,
+    pub switches: Vec<Switch>,
+    /// in the order of the map
+    pub hazards: Vec<HazardState>,
+    #[serde(default)]
+    pub hook: Option<HookState>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Switch {
+    pub id: usize,
+    pub pressed: bool,
+    pub used: bool,
+    pub open: bool,
+    pub timer: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HazardState {
+    #[serde(with = "vector")]
+    pub pos: [f64; 2],
+    /// next point of a patrol
+    pub next: usize,
+}
+
+/// toml can't write fixed size arrays, vectors are written as sequences of two floats
+mod vector {
+    use serde::{Serializer, Deserializer, Deserialize};
+    use serde::ser::SerializeSeq;
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(vector: &[f64; 2], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(2))?;
+        seq.serialize_element(&vector[0])?;
+        seq.serialize_element(&vector[1])?;
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[f64; 2], D::Error> {
+        let vector = Vec::<f64>::deserialize(deserializer)?;
+        if vector.len() != 2 {
+            return Err(D::Error::invalid_length(vector.len(), &"two coordinates"))
+        }
+        Ok([vector[0], vector[1]])
+    }
+}
+
+/// Grappling hook attached to a wall
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HookState {
+    pub wall: usize,
+    #[serde(with = "vector")]
+    pub anchor: [f64; 2],
+    pub length: f64,
+}
+
+/// Save states of each level by slot
+#[derive(Serialize, Deserialize, Default)]
+pub struct SaveStates {
+    #[serde(default)]
+    pub levels: BTreeMap<String, BTreeMap<String, SaveState>>,
+}
+
+impl SaveStates {
+    /// Save states are empty if the file doesn't exist
+    pub fn load() -> Result<SaveStates, Error> {
+        toml_file::load(SAVE_STATES_FILE)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        toml_file::save(SAVE_STATES_FILE, self)
+    }
+
+    pub fn get(&self, level: &str, slot: usize) -> Option<&SaveState> {
+        self.levels.get(level).and_then(|slots| slots.get(&slot.to_string()))
+    }
+
+    pub fn insert(&mut self, level: &str, slot: usize, state: SaveState) {
+        self.levels.entry(level.to_string())
+            .or_insert_with(BTreeMap::new)
+            .insert(slot.to_string(), state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate toml;
+
+    use super::*;
+
+    #[test]
+    fn save_states_round_trip() {
+        let state = SaveState {
+            ball_pos: [1.5, -2.],
+            ball_vel: [3., 4.],
+            ball_acc: [0., -15.],
+            ball_radius: 0.5,
+            ball_angle: 1.,
+            ball_spin: -2.,
+            air_jump: true,
+            size_timer: Some(1.),
+            in_water: false,
+            gravity_angle: -1.5,
+            camera_angle: 0.,
+            run_time: 12.,
+            jumps: 7,
+            ticks: 720,
+            lives: Some(2),
+            disabled: vec!(3, 4),
+            gravity_triggers: vec!(5),
+            replay: vec!(ReplayJump { tick: 10, angle: 0.5 }),
+            switches: vec!(Switch { id: 2, pressed: true, used: true, open: true, timer: None }),
+            hazards: vec!(HazardState { pos: [6., 7.], next: 1 }),
+            hook: Some(HookState { wall: 8, anchor: [9., 10.], length: 4. }),
+        };
+        let mut save_states = SaveStates::default();
+        save_states.insert("map", 0, state);
+
+        let text = toml::to_string(&save_states).unwrap();
+        let loaded: SaveStates = toml::from_str(&text).unwrap();
+        let state = loaded.get("map", 0).unwrap();
+        assert_eq!(state.ball_pos, [1.5, -2.]);
+        assert_eq!(state.ball_vel, [3., 4.]);
+        assert_eq!(state.ball_acc, [0., -15.]);
+        assert_eq!(state.size_timer, Some(1.));
+        assert_eq!(state.disabled, vec!(3, 4));
+        assert_eq!(state.replay[0].tick, 10);
+        assert_eq!(state.switches[0].timer, None);
+        assert_eq!(state.hazards[0].pos, [6., 7.]);
+        assert_eq!(state.lives, Some(2));
+        assert_eq!(state.hook.as_ref().map(|hook| (hook.wall, hook.anchor)), Some((8, [9., 10.])));
+        assert!(loaded.get("map", 1).is_none());
+    }
+}
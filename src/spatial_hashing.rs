@@ -3,7 +3,11 @@ extern crate fnv;
 use physics::Body;
 
 pub struct SpatialHashing {
-    map: fnv::FnvHashMap<[i32; 2], Vec<(usize, Body)>>,
+    map: fnv::FnvHashMap<[i32; 2], Vec<usize>>,
+    bodies: Vec<Body>,
+    disabled: fnv::FnvHashSet<usize>,
+    /// ids of removed bodies, reused by insert
+    free: fnv::FnvHashSet<usize>,
     unit: f64
 }
 
@@ -12,29 +16,143 @@ impl SpatialHashing {
         let mut map = fnv::FnvHashMap::default();
         for (id, body) in bodies.iter().enumerate() {
             for cell in body.cells(unit) {
-                let mut vec = map.entry(cell).or_insert(vec!());
-                vec.push((id,body.clone()));
+                let vec = map.entry(cell).or_insert(vec!());
+                vec.push(id);
             }
         }
         SpatialHashing {
             map: map,
+            bodies: bodies.clone(),
+            disabled: fnv::FnvHashSet::default(),
+            free: fnv::FnvHashSet::default(),
             unit: unit,
         }
     }
-    pub fn get_on_body(&self, body: &Body) -> Vec<Body> {
+    /// ids of enabled bodies that share a cell with body
+    pub fn get_on_body(&self, body: &Body) -> Vec<usize> {
+        self.get_all_on_body(body).into_iter()
+            .filter(|id| !self.disabled.contains(id))
+            .collect()
+    }
+    /// ids of bodies that share a cell with body, disabled ones included
+    pub fn get_all_on_body(&self, body: &Body) -> Vec<usize> {
         let mut res = Vec::new();
         let mut ids = fnv::FnvHashSet::default();
 
         for cell in body.cells(self.unit) {
             if let Some(vec) = self.map.get(&cell) {
-                for &(id, ref body) in vec.iter() {
+                for &id in vec.iter() {
                     if !ids.contains(&id) {
                         ids.insert(id);
-                        res.push(body.clone());
+                        res.push(id);
                     }
                 }
             }
         }
         res
     }
+    /// Add a body and return its id, the id of a removed body is reused if any
+    pub fn insert(&mut self, body: Body) -> usize {
+        let free = self.free.iter().next().cloned();
+        let id = match free {
+            Some(id) => {
+                self.free.remove(&id);
+                self.disabled.remove(&id);
+                id
+            },
+            None => self.bodies.len(),
+        };
+        for cell in body.cells(self.unit) {
+            self.map.entry(cell).or_insert(vec!()).push(id);
+        }
+        if id == self.bodies.len() {
+            self.bodies.push(body);
+        } else {
+            self.bodies[id] = body;
+        }
+        id
+    }
+    /// Remove the body from the cells, it is not enabled until its id is reused
+    pub fn remove(&mut self, id: usize) {
+        for cell in self.bodies[id].cells(self.unit) {
+            let empty = match self.map.get_mut(&cell) {
+                Some(vec) => {
+                    vec.retain(|&other| other != id);
+                    vec.is_empty()
+                },
+                None => false,
+            };
+            if empty {
+                self.map.remove(&cell);
+            }
+        }
+        self.free.insert(id);
+    }
+    /// First enabled body hit by the ray for which filter is true, with the distance,
+    /// cells are walked along the ray so only bodies near it are tested
+    pub fn raycast<F: Fn(usize) -> bool>(&self, origin: [f64; 2], direction: [f64; 2], length: f64, filter: F) -> Option<(usize, f64)> {
+        let mut cell = [(origin[0]/self.unit).floor() as i32, (origin[1]/self.unit).floor() as i32];
+        let mut step = [0; 2];
+        // distance along the ray to cross a cell and to the next border
+        let mut delta = [::std::f64::INFINITY; 2];
+        let mut next = [::std::f64::INFINITY; 2];
+        for axis in 0..2 {
+            if direction[axis] > 0. {
+                step[axis] = 1;
+                delta[axis] = self.unit/direction[axis];
+                next[axis] = ((cell[axis] + 1) as f64*self.unit - origin[axis])/direction[axis];
+            } else if direction[axis] < 0. {
+                step[axis] = -1;
+                delta[axis] = -self.unit/direction[axis];
+                next[axis] = (cell[axis] as f64*self.unit - origin[axis])/direction[axis];
+            }
+        }
+
+        let mut best: Option<(usize, f64)> = None;
+        loop {
+            if let Some(vec) = self.map.get(&cell) {
+                for &id in vec {
+                    if self.disabled.contains(&id) || !filter(id) {
+                        continue
+                    }
+                    if let Some(distance) = self.bodies[id].raycast(origin, direction, length) {
+                        if best.map_or(true, |(_, best)| distance < best) {
+                            best = Some((id, distance));
+                        }
+                    }
+                }
+            }
+            // later cells are farther than a hit before the exit of this cell
+            let exit = next[0].min(next[1]);
+            if exit > length || best.map_or(false, |(_, distance)| distance <= exit) {
+                return best
+            }
+            let axis = if next[0] < next[1] { 0 } else { 1 };
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+    }
+    #[inline]
+    pub fn get(&self, id: usize) -> &Body {
+        &self.bodies[id]
+    }
+    #[inline]
+    pub fn is_enabled(&self, id: usize) -> bool {
+        !self.disabled.contains(&id) && !self.free.contains(&id)
+    }
+    /// a disabled body is ignored by get_on_body
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) {
+        if enabled {
+            self.disabled.remove(&id);
+        } else {
+            self.disabled.insert(id);
+        }
+    }
+    pub fn enable_all(&mut self) {
+        self.disabled.clear();
+    }
+    /// ids of disabled bodies, removed ones excluded
+    pub fn disabled(&self) -> Vec<usize> {
+        self.disabled.iter().cloned().filter(|id| !self.free.contains(id)).collect()
+    }
 }
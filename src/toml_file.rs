@@ -0,0 +1,42 @@
+extern crate toml;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+pub enum Error {
+    Io(&'static str, ::std::io::Error),
+    TomlDe(&'static str, toml::de::Error),
+    TomlSer(&'static str, toml::ser::Error),
+}
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        use self::Error::*;
+        match *self {
+            Io(file, ref e) => write!(fmt, "file `{}`: io error: {}", file, e),
+            TomlDe(file, ref e) => write!(fmt, "file `{}`: toml decode error: {}", file, e),
+            TomlSer(file, ref e) => write!(fmt, "file `{}`: toml encode error: {}", file, e),
+        }
+    }
+}
+
+/// The value is the default if the file doesn't exist
+pub fn load<T: DeserializeOwned + Default>(file: &'static str) -> Result<T, Error> {
+    use std::fs::File;
+    use std::io::{Read, ErrorKind};
+    let mut text = String::new();
+    match File::open(file) {
+        Ok(mut f) => f.read_to_string(&mut text).map_err(|e| Error::Io(file, e))?,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(Error::Io(file, e)),
+    };
+    toml::from_str(&text).map_err(|e| Error::TomlDe(file, e))
+}
+
+pub fn save<T: Serialize>(file: &'static str, value: &T) -> Result<(), Error> {
+    use std::fs::File;
+    use std::io::Write;
+    let text = toml::to_string(value).map_err(|e| Error::TomlSer(file, e))?;
+    File::create(file)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .map_err(|e| Error::Io(file, e))
+}